@@ -0,0 +1,217 @@
+use std::io;
+use futures_util::stream::{self,Stream};
+use tokio::io::{AsyncBufRead,AsyncBufReadExt};
+use crate::error::{Error,Position,Result};
+use crate::headers::{Headers,Row};
+use crate::push_parser::SimpleCsvPushParser;
+use crate::reader::SimpleCsvReaderOptions;
+use crate::record::{ByteRecord,StringRecord};
+use crate::writer::NewlineType;
+
+/// Reads rows from a `tokio::io::AsyncBufRead` with the same parser and options as `SimpleCsvReader`.
+///
+/// Every method can be cancelled: input that was already read stays with the reader,
+/// so calling the method again carries on with the same row.
+pub struct AsyncSimpleCsvReader<R: AsyncBufRead + Unpin> {
+    parser: SimpleCsvPushParser,
+    input_reader: R
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncSimpleCsvReader<R> {
+    pub fn new(buffer: R) -> AsyncSimpleCsvReader<R> {
+        AsyncSimpleCsvReader::with_options(buffer,Default::default())
+    }
+
+    pub fn with_options(buffer: R, options: SimpleCsvReaderOptions) -> AsyncSimpleCsvReader<R> {
+        AsyncSimpleCsvReader {
+            parser: SimpleCsvPushParser::new(options),
+            input_reader: buffer
+        }
+    }
+
+    /// Returns the header row, reading it first if no row has been read yet.
+    /// Returns `None` if the reader was not configured with `has_headers` or the input is empty.
+    pub async fn headers(&mut self) -> Option<Result<&Headers>> {
+        if let Err(e) = self.read_headers().await {
+            return Some(Err(e));
+        }
+        self.parser.headers().map(Ok)
+    }
+
+    async fn read_headers(&mut self) -> Result<()> {
+        if !self.parser.wants_headers() {
+            return Ok(());
+        }
+        let row = self.read_row().await;
+        self.parser.set_headers(row)
+    }
+
+    pub async fn next_row(&mut self) -> Option<Result<&[String]>> {
+        if let Err(e) = self.read_headers().await {
+            return Some(Err(e));
+        }
+        match self.read_row().await? {
+            Ok(..) => Some(self.parser.row()),
+            Err(e) => Some(Err(e))
+        }
+    }
+
+    /// Like `next_row`, but unquoted fields listed in `nulls` are `None`
+    pub async fn next_nullable_row(&mut self) -> Option<Result<&[Option<String>]>> {
+        if let Err(e) = self.read_headers().await {
+            return Some(Err(e));
+        }
+        match self.read_row().await? {
+            Ok(..) => Some(self.parser.nullable_row()),
+            Err(e) => Some(Err(e))
+        }
+    }
+
+    /// Like `next_row`, but the returned row can also be indexed by column name when `has_headers` is set
+    pub async fn next_named_row(&mut self) -> Option<Result<Row<'_>>> {
+        if let Err(e) = self.read_headers().await {
+            return Some(Err(e));
+        }
+        match self.read_row().await? {
+            Ok(..) => Some(self.parser.named_row()),
+            Err(e) => Some(Err(e))
+        }
+    }
+
+    /// Like `next_row`, but returns the fields as raw bytes without decoding them as UTF-8
+    pub async fn next_byte_record(&mut self) -> Option<Result<&ByteRecord>> {
+        if let Err(e) = self.read_headers().await {
+            return Some(Err(e));
+        }
+        match self.read_row().await? {
+            Ok(..) => Some(Ok(self.parser.byte_record())),
+            Err(e) => Some(Err(e))
+        }
+    }
+
+    /// Like `next_row`, but borrows every field from a single buffer that is reused from row to row
+    pub async fn next_record(&mut self) -> Option<Result<&StringRecord>> {
+        if let Err(e) = self.read_headers().await {
+            return Some(Err(e));
+        }
+        match self.read_row().await? {
+            Ok(..) => Some(self.parser.string_record()),
+            Err(e) => Some(Err(e))
+        }
+    }
+
+    /// Turns the reader into a `Stream` of rows, like iterating over a `SimpleCsvReader`
+    pub fn into_stream(self) -> impl Stream<Item = Result<Vec<String>>> {
+        stream::unfold(self, |mut reader| async move {
+            if let Err(e) = reader.read_headers().await {
+                return Some((Err(e),reader));
+            }
+            let row = match reader.read_row().await? {
+                Ok(..) => reader.parser.take_row(),
+                Err(e) => Err(e)
+            };
+            Some((row,reader))
+        })
+    }
+
+    /// Position of the start of the most recently read row
+    pub fn position(&self) -> Position {
+        self.parser.position()
+    }
+
+    /// Whether each field of the most recently read row was enclosed in quotes
+    pub fn quoted_fields(&self) -> &[bool] {
+        self.parser.quoted_fields()
+    }
+
+    /// Violations found in the most recently read row when `strictness` is `Strictness::Warn`
+    pub fn warnings(&self) -> &[Error] {
+        self.parser.warnings()
+    }
+
+    /// Comment lines skipped right before the most recently read row, without the comment character and line ending
+    pub fn comments(&self) -> &[String] {
+        self.parser.comments()
+    }
+
+    /// The line ending of the first row that ended with one, in the form `SimpleCsvWriterOptions::newline_type` takes
+    pub fn newline_type(&self) -> Option<&NewlineType> {
+        self.parser.newline_type()
+    }
+
+    async fn read_row(&mut self) -> Option<Result<()>> {
+        // Feed the parser straight from the reader's buffer until it completes a record
+        loop {
+            let (consumed, done) = {
+                let input = match self.input_reader.fill_buf().await {
+                    Ok(input) => input,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Some(Err(Error::Io { error: e, pos: self.parser.next_position() }))
+                };
+                if input.is_empty() {
+                    // EOF. Returns the last record if it did not end with a newline
+                    if self.parser.end_input() {
+                        break;
+                    }
+                    return None;
+                }
+                self.parser.parse(input)
+            };
+            self.input_reader.consume(consumed);
+            if done {
+                break;
+            }
+        }
+        Some(self.parser.record_result())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+    use tokio::io::{self,AsyncWriteExt,BufReader};
+    use crate::reader::{SimpleCsvReader,Strictness};
+
+    fn row(fields: &[&str]) -> Vec<String> {
+        fields.iter().map(|field| field.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn async_reader_duplex() {
+        let (mut writer, reader) = io::duplex(4);
+        let mut reader = AsyncSimpleCsvReader::new(BufReader::new(reader));
+        tokio::spawn(async move {
+            // Split tokens and a quoted newline across writes
+            for part in &["1,\"a\r", "\nb\",3\r", "\n4,\"\"\"5\"", "\"\",6"] {
+                writer.write_all(part.as_bytes()).await.unwrap();
+            }
+        });
+
+        assert_eq!(reader.next_row().await.unwrap().unwrap(), &*row(&["1","a\r\nb","3"]));
+        assert_eq!(reader.position(), Position { byte: 0, line: 1, record: 0 });
+        assert_eq!(reader.next_row().await.unwrap().unwrap(), &*row(&["4","\"5\"","6"]));
+        assert_eq!(reader.position(), Position { byte: 12, line: 3, record: 1 });
+        assert!(reader.next_row().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn async_reader_matches_sync_reader() {
+        let input = b"id,name\r\n1,\"x\"y\r\n2,\xff\r\n\"3\",\"z".repeat(20);
+        let options = SimpleCsvReaderOptions { has_headers: true, strictness: Strictness::Strict, ..Default::default() };
+        let expected: Vec<_> = SimpleCsvReader::with_options(&*input,options.clone())
+            .map(|row| row.map_err(|e| e.to_string()))
+            .collect();
+
+        let (mut writer, reader) = io::duplex(7);
+        tokio::spawn(async move {
+            writer.write_all(&input).await.unwrap();
+        });
+        let mut reader = AsyncSimpleCsvReader::with_options(BufReader::new(reader),options);
+        assert_eq!(reader.headers().await.unwrap().unwrap().index_of("name"), Ok(1));
+        let rows: Vec<_> = reader.into_stream()
+            .map(|row| row.map_err(|e| e.to_string()))
+            .collect().await;
+        assert_eq!(rows, expected);
+    }
+}
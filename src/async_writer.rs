@@ -0,0 +1,177 @@
+use std::future::poll_fn;
+use std::io::{self,Result};
+use std::pin::Pin;
+use std::task::{ready,Context,Poll};
+use futures_sink::Sink;
+use tokio::io::AsyncWrite;
+use crate::writer::{encode_row,fit_row,Field,SimpleCsvWriterOptions};
+
+// The sink writes its buffered rows out once they reach this size
+const SINK_BUFFER_LIMIT: usize = 8 * 1024;
+
+/// Writes rows to a `tokio::io::AsyncWrite`, quoting fields the same way as `SimpleCsvWriter`.
+///
+/// Rows are encoded into a buffer and then written out. If a write is cancelled,
+/// the rest of its row is written before the next row or by `flush`.
+pub struct AsyncSimpleCsvWriter<W: AsyncWrite + Unpin> {
+    options: SimpleCsvWriterOptions,
+    writer: W,
+    row_written: bool,
+    // The number of fields in the first row written, for `column_count`
+    columns: Option<usize>,
+    buffer: Vec<u8>,
+    // Bytes at the start of `buffer` that have already been written
+    written: usize
+}
+
+impl<W: AsyncWrite + Unpin> AsyncSimpleCsvWriter<W> {
+    pub fn new(writer: W) -> AsyncSimpleCsvWriter<W> {
+        AsyncSimpleCsvWriter::with_options(writer,Default::default())
+    }
+
+    pub fn with_options(writer: W, options: SimpleCsvWriterOptions) -> AsyncSimpleCsvWriter<W> {
+        AsyncSimpleCsvWriter {
+            options,
+            writer,
+            row_written: false,
+            columns: None,
+            buffer: Vec::new(),
+            written: 0
+        }
+    }
+
+    /// Returns the underlying writer. Rows that have not been fully written yet are dropped
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    pub async fn write(&mut self, row: &[String]) -> Result<()> {
+        self.encode(row)?;
+        poll_fn(|cx| self.poll_write_buffer(cx)).await
+    }
+
+    /// Like `write`, but writes `None` as `null`, telling it apart from an empty string
+    pub async fn write_nullable(&mut self, row: &[Option<String>]) -> Result<()> {
+        self.encode(row)?;
+        poll_fn(|cx| self.poll_write_buffer(cx)).await
+    }
+
+    pub async fn write_all(&mut self, rows: &[Vec<String>]) -> Result<()> {
+        for row in rows.iter() {
+            self.write(row).await?;
+        }
+        Ok(())
+    }
+
+    /// Writes out anything still buffered and flushes the underlying writer
+    pub async fn flush(&mut self) -> Result<()> {
+        poll_fn(|cx| self.poll_flush_all(cx)).await
+    }
+
+    fn encode<F: Field>(&mut self, row: &[F]) -> Result<()> {
+        let row = fit_row(&self.options,self.columns,row)?;
+        encode_row(&mut self.buffer,&self.options,&row,self.row_written)?;
+        self.row_written = true;
+        self.columns.get_or_insert(row.len());
+        Ok(())
+    }
+
+    fn poll_write_buffer(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        while self.written < self.buffer.len() {
+            let n = ready!(Pin::new(&mut self.writer).poll_write(cx,&self.buffer[self.written..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.written += n;
+        }
+        self.buffer.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_flush_all(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        ready!(self.poll_write_buffer(cx))?;
+        Pin::new(&mut self.writer).poll_flush(cx)
+    }
+}
+
+/// Rows sent to the sink are buffered and written out in batches.
+/// `flush` and `close` write out everything that is left.
+impl<W: AsyncWrite + Unpin> Sink<Vec<String>> for AsyncSimpleCsvWriter<W> {
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        if this.buffer.len() >= SINK_BUFFER_LIMIT {
+            return this.poll_write_buffer(cx);
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, row: Vec<String>) -> Result<()> {
+        self.get_mut().encode(&row)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get_mut().poll_flush_all(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_buffer(cx))?;
+        Pin::new(&mut this.writer).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::{stream,SinkExt,StreamExt};
+    use tokio::io::{self,AsyncReadExt};
+    use crate::writer::{NewlineType,SimpleCsvWriter};
+
+    fn rows() -> Vec<Vec<String>> {
+        (0..500).map(|i| vec![i.to_string(),format!("a \"{}\"",i),"b,\r\nc".to_string()]).collect()
+    }
+
+    fn sync_output(options: SimpleCsvWriterOptions) -> Vec<u8> {
+        let mut writer = SimpleCsvWriter::with_options(Vec::new(),options);
+        writer.write_all(&rows()).unwrap();
+        writer.as_inner()
+    }
+
+    #[tokio::test]
+    async fn async_writer_matches_sync_writer() {
+        let (writer, mut reader) = io::duplex(16);
+        let options = SimpleCsvWriterOptions { delimiter: ';', newline_type: NewlineType::WindowsStyle, ..Default::default() };
+        let task = tokio::spawn(async move {
+            let mut output = Vec::new();
+            reader.read_to_end(&mut output).await.unwrap();
+            output
+        });
+
+        let mut writer = AsyncSimpleCsvWriter::with_options(writer,options);
+        writer.write_all(&rows()).await.unwrap();
+        writer.flush().await.unwrap();
+        drop(writer);
+
+        let options = SimpleCsvWriterOptions { delimiter: ';', newline_type: NewlineType::WindowsStyle, ..Default::default() };
+        assert_eq!(task.await.unwrap(), sync_output(options));
+    }
+
+    #[tokio::test]
+    async fn async_writer_sink() {
+        let (writer, mut reader) = io::duplex(16);
+        let task = tokio::spawn(async move {
+            let mut output = Vec::new();
+            reader.read_to_end(&mut output).await.unwrap();
+            output
+        });
+
+        let mut writer = AsyncSimpleCsvWriter::new(writer);
+        writer.send_all(&mut stream::iter(rows()).map(Ok)).await.unwrap();
+        writer.close().await.unwrap();
+
+        assert_eq!(task.await.unwrap(), sync_output(Default::default()));
+    }
+}
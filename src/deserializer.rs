@@ -0,0 +1,428 @@
+use std::error;
+use std::fmt;
+use std::io::BufRead;
+use std::marker::PhantomData;
+
+use serde::de::{self,DeserializeOwned,DeserializeSeed,IntoDeserializer,Visitor};
+use serde::forward_to_deserialize_any;
+
+use crate::error::Error;
+use crate::headers::{Headers,Row};
+use crate::reader::SimpleCsvReader;
+
+#[derive(Debug)]
+pub enum DeserializeErrorKind {
+    /// Reading the row from the input failed
+    Read(Error),
+    /// The row was read but could not be converted to the requested type
+    Message(String)
+}
+
+/// An error produced by `SimpleCsvReader::deserialize`.
+/// `row` counts data rows from 0 and does not include the header row.
+/// `column` is set when the failure can be attributed to a single field.
+#[derive(Debug)]
+pub struct DeserializeError {
+    row: usize,
+    column: Option<usize>,
+    header: Option<String>,
+    kind: DeserializeErrorKind
+}
+
+impl DeserializeError {
+    pub fn row(&self) -> usize {
+        self.row
+    }
+
+    pub fn column(&self) -> Option<usize> {
+        self.column
+    }
+
+    /// The header name of the failing column, if headers were read
+    pub fn header(&self) -> Option<&str> {
+        self.header.as_deref()
+    }
+
+    pub fn kind(&self) -> &DeserializeErrorKind {
+        &self.kind
+    }
+}
+
+impl fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,"row {}",self.row)?;
+        if let Some(column) = self.column {
+            write!(f,", column {}",column)?;
+        }
+        if let Some(ref header) = self.header {
+            write!(f," (\"{}\")",header)?;
+        }
+        match self.kind {
+            DeserializeErrorKind::Read(ref e) => write!(f,": {}",e),
+            DeserializeErrorKind::Message(ref message) => write!(f,": {}",message)
+        }
+    }
+}
+
+impl error::Error for DeserializeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self.kind {
+            DeserializeErrorKind::Read(ref e) => Some(e),
+            DeserializeErrorKind::Message(..) => None
+        }
+    }
+}
+
+// Error type used while walking a single row. Row and column are attached afterwards.
+#[derive(Debug)]
+struct FieldError(String);
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl error::Error for FieldError {}
+
+impl de::Error for FieldError {
+    fn custom<T: fmt::Display>(msg: T) -> FieldError {
+        FieldError(msg.to_string())
+    }
+}
+
+/// Iterator returned by `SimpleCsvReader::deserialize`
+pub struct DeserializeRows<'r, B: BufRead + 'r, T> {
+    reader: &'r mut SimpleCsvReader<B>,
+    row: usize,
+    _marker: PhantomData<T>
+}
+
+impl<B: BufRead> SimpleCsvReader<B> {
+    /// Returns an iterator that deserializes each remaining row into a `T`.
+    /// Structs and maps are filled by header name when `has_headers` is set, and by position otherwise.
+    /// Empty fields deserialize to `None` when the target is an `Option`.
+    pub fn deserialize<T: DeserializeOwned>(&mut self) -> DeserializeRows<'_, B, T> {
+        DeserializeRows {
+            reader: self,
+            row: 0,
+            _marker: PhantomData
+        }
+    }
+}
+
+impl<'r, B: BufRead, T: DeserializeOwned> Iterator for DeserializeRows<'r, B, T> {
+    type Item = Result<T,DeserializeError>;
+
+    fn next(&mut self) -> Option<Result<T,DeserializeError>> {
+        let row_index = self.row;
+        let result = match self.reader.next_named_row()? {
+            Ok(row) => deserialize_row(&row,row_index),
+            Err(e) => Err(DeserializeError {
+                row: row_index,
+                column: None,
+                header: None,
+                kind: DeserializeErrorKind::Read(e)
+            })
+        };
+        self.row += 1;
+        Some(result)
+    }
+}
+
+fn deserialize_row<T: DeserializeOwned>(row: &Row, row_index: usize) -> Result<T,DeserializeError> {
+    let mut deserializer = RowDeserializer {
+        fields: row.as_slice(),
+        headers: row.headers(),
+        column: 0,
+        current: None
+    };
+    T::deserialize(&mut deserializer).map_err(|FieldError(message)| {
+        let header = match (deserializer.current, deserializer.headers) {
+            (Some(column), Some(headers)) if column < headers.len() => Some(headers[column].to_string()),
+            _ => None
+        };
+        DeserializeError {
+            row: row_index,
+            column: deserializer.current,
+            header,
+            kind: DeserializeErrorKind::Message(message)
+        }
+    })
+}
+
+struct RowDeserializer<'a> {
+    fields: &'a [String],
+    headers: Option<&'a Headers>,
+    // Next column to hand out
+    column: usize,
+    // Column being deserialized, if any. Used to locate errors.
+    current: Option<usize>
+}
+
+impl<'a> RowDeserializer<'a> {
+    fn next_field(&mut self) -> Option<&'a str> {
+        let field = self.fields.get(self.column)?;
+        self.current = Some(self.column);
+        self.column += 1;
+        Some(field)
+    }
+}
+
+impl<'a, 'de, 'r> de::Deserializer<'de> for &'r mut RowDeserializer<'a> {
+    type Error = FieldError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,FieldError> {
+        match self.headers {
+            Some(..) => visitor.visit_map(self),
+            None => visitor.visit_seq(self)
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,FieldError> {
+        visitor.visit_seq(self)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value,FieldError> {
+        visitor.visit_seq(self)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self, _name: &'static str, _len: usize, visitor: V) -> Result<V::Value,FieldError> {
+        visitor.visit_seq(self)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,FieldError> {
+        match self.headers {
+            Some(..) => visitor.visit_map(self),
+            None => Err(de::Error::custom("cannot deserialize a map from a row without headers"))
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, _name: &'static str, _fields: &'static [&'static str], visitor: V) -> Result<V::Value,FieldError> {
+        self.deserialize_any(visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct enum identifier ignored_any
+    }
+}
+
+impl<'a, 'de, 'r> de::SeqAccess<'de> for &'r mut RowDeserializer<'a> {
+    type Error = FieldError;
+
+    fn next_element_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<Option<S::Value>,FieldError> {
+        match self.next_field() {
+            Some(field) => {
+                let value = seed.deserialize(FieldDeserializer(field))?;
+                self.current = None;
+                Ok(Some(value))
+            },
+            None => Ok(None)
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.fields.len() - self.column)
+    }
+}
+
+impl<'a, 'de, 'r> de::MapAccess<'de> for &'r mut RowDeserializer<'a> {
+    type Error = FieldError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>,FieldError> {
+        let headers = match self.headers {
+            Some(headers) => headers,
+            None => return Ok(None)
+        };
+        // Fields past the end of the header row have no name and are skipped
+        if self.column >= headers.len() || self.column >= self.fields.len() {
+            return Ok(None);
+        }
+        self.current = Some(self.column);
+        let key: de::value::StrDeserializer<FieldError> = headers[self.column].into_deserializer();
+        seed.deserialize(key).map(Some)
+    }
+
+    fn next_value_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<S::Value,FieldError> {
+        let field = match self.next_field() {
+            Some(field) => field,
+            None => return Err(de::Error::custom("row has fewer fields than headers"))
+        };
+        let value = seed.deserialize(FieldDeserializer(field))?;
+        self.current = None;
+        Ok(value)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.headers.map(|headers| headers.len().min(self.fields.len()) - self.column)
+    }
+}
+
+struct FieldDeserializer<'a>(&'a str);
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,FieldError> {
+                match self.0.parse() {
+                    Ok(value) => visitor.$visit(value),
+                    Err(e) => Err(de::Error::custom(format!("could not parse \"{}\": {}",self.0,e)))
+                }
+            }
+        )*
+    }
+}
+
+impl<'a, 'de> de::Deserializer<'de> for FieldDeserializer<'a> {
+    type Error = FieldError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,FieldError> {
+        visitor.visit_str(self.0)
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,FieldError> {
+        visitor.visit_bytes(self.0.as_bytes())
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,FieldError> {
+        visitor.visit_bytes(self.0.as_bytes())
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,FieldError> {
+        if self.0.is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,FieldError> {
+        if self.0.is_empty() {
+            visitor.visit_unit()
+        } else {
+            Err(de::Error::custom(format!("expected an empty field, found \"{}\"",self.0)))
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value,FieldError> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value,FieldError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value,FieldError> {
+        let variant: de::value::StrDeserializer<FieldError> = self.0.into_deserializer();
+        visitor.visit_enum(variant)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,FieldError> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        str string seq tuple tuple_struct map struct identifier
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_derive::Deserialize;
+    use crate::reader::{SimpleCsvReader,SimpleCsvReaderOptions};
+    use super::DeserializeErrorKind;
+
+    #[derive(Debug,PartialEq,Deserialize)]
+    enum Color {
+        Red,
+        Green
+    }
+
+    #[derive(Debug,PartialEq,Deserialize)]
+    struct Record {
+        name: String,
+        age: u32,
+        score: Option<f64>,
+        active: bool,
+        color: Color
+    }
+
+    #[test]
+    fn deserialize_by_header_name() {
+        let test_string = "age,name,active,score,color\r\n30,Ann,true,1.5,Red\r\n41,Bob,false,,Green".to_string();
+        let bytes = test_string.into_bytes();
+        let csv_options = SimpleCsvReaderOptions { has_headers: true, ..Default::default() };
+        let mut reader = SimpleCsvReader::with_options(&*bytes,csv_options);
+
+        let records: Vec<Record> = reader.deserialize().map(|r| r.unwrap()).collect();
+        assert_eq!(records, vec![
+            Record { name: "Ann".to_string(), age: 30, score: Some(1.5), active: true, color: Color::Red },
+            Record { name: "Bob".to_string(), age: 41, score: None, active: false, color: Color::Green }]);
+    }
+
+    #[test]
+    fn deserialize_by_position() {
+        let test_string = "Ann,30,1.5,true,Red\r\nBob,41,,false,Green".to_string();
+        let bytes = test_string.into_bytes();
+        let mut reader = SimpleCsvReader::new(&*bytes);
+
+        let records: Vec<(String,u8,Option<f32>,bool,Color)> = reader.deserialize().map(|r| r.unwrap()).collect();
+        assert_eq!(records[1], ("Bob".to_string(),41,None,false,Color::Green));
+
+        let bytes = "Ann,30,1.5,true,Red".to_string().into_bytes();
+        let mut reader = SimpleCsvReader::new(&*bytes);
+        let record: Record = reader.deserialize().next().unwrap().unwrap();
+        assert_eq!(record.name, "Ann");
+    }
+
+    #[test]
+    fn deserialize_error_position() {
+        let test_string = "name,age,active,score,color\r\nAnn,30,true,,Red\r\nBob,forty,true,,Red".to_string();
+        let bytes = test_string.into_bytes();
+        let csv_options = SimpleCsvReaderOptions { has_headers: true, ..Default::default() };
+        let mut reader = SimpleCsvReader::with_options(&*bytes,csv_options);
+        let mut rows = reader.deserialize::<Record>();
+
+        assert!(rows.next().unwrap().is_ok());
+        let error = rows.next().unwrap().unwrap_err();
+        assert_eq!(error.row(), 1);
+        assert_eq!(error.column(), Some(1));
+        assert_eq!(error.header(), Some("age"));
+        match *error.kind() {
+            DeserializeErrorKind::Message(ref message) => assert!(message.contains("forty")),
+            DeserializeErrorKind::Read(..) => panic!("unexpected read error")
+        }
+        assert!(rows.next().is_none());
+    }
+
+    #[test]
+    fn deserialize_missing_column() {
+        let test_string = "name,age\r\nAnn,30".to_string();
+        let bytes = test_string.into_bytes();
+        let csv_options = SimpleCsvReaderOptions { has_headers: true, ..Default::default() };
+        let mut reader = SimpleCsvReader::with_options(&*bytes,csv_options);
+
+        let error = reader.deserialize::<Record>().next().unwrap().unwrap_err();
+        assert_eq!(error.row(), 0);
+        assert_eq!(error.column(), None);
+        assert!(error.to_string().contains("active"));
+    }
+}
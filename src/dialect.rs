@@ -0,0 +1,187 @@
+use crate::reader::{RecordTerminator,SimpleCsvReaderOptions};
+use crate::writer::{NewlineType,SimpleCsvWriterOptions};
+
+/// The format of a CSV file, shared by the reader and the writer so that their options cannot drift apart.
+///
+/// Both `SimpleCsvReaderOptions` and `SimpleCsvWriterOptions` can be made from a `Dialect`.
+/// A file read with a preset is written back byte for byte with the same preset,
+/// as long as it was written the way the preset describes: quoting only the fields that need it,
+/// and ending every row with the line ending.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct Dialect {
+    pub delimiter: char,
+    /// The quote around fields that need it, or `None` for formats that never quote
+    pub text_enclosure: Option<char>,
+    /// The character that escapes quotes, delimiters and line endings.
+    /// With `None` quotes inside a quoted field are doubled
+    pub escape: Option<char>,
+    /// The line ending after every row
+    pub newline_type: NewlineType,
+    /// The field that stands for null, for `next_nullable_row` and `write_nullable`.
+    /// `None` for formats without nulls, where every field reads back as `Some`
+    pub null: Option<String>
+}
+
+impl Dialect {
+    /// Comma separated, `"` quotes doubled inside quoted fields and `\r\n` line endings, as in RFC 4180
+    pub fn rfc4180() -> Dialect {
+        Dialect {
+            delimiter: ',',
+            text_enclosure: Some('"'),
+            escape: None,
+            newline_type: NewlineType::WindowsStyle,
+            null: None
+        }
+    }
+
+    /// What Excel writes when saving as CSV, which follows RFC 4180. An alias for `rfc4180`
+    pub fn excel() -> Dialect {
+        Dialect::rfc4180()
+    }
+
+    /// What Excel writes when saving as tab separated text
+    pub fn excel_tab() -> Dialect {
+        Dialect {
+            delimiter: '\t',
+            ..Dialect::excel()
+        }
+    }
+
+    /// RFC 4180 with `\n` line endings
+    pub fn unix() -> Dialect {
+        Dialect {
+            newline_type: NewlineType::UnixStyle,
+            ..Dialect::rfc4180()
+        }
+    }
+
+    /// `COPY ... WITH (FORMAT csv)` in PostgreSQL. Unix, except that an empty unquoted field is null
+    /// and an empty string is written as `""`
+    pub fn postgresql_csv() -> Dialect {
+        Dialect {
+            null: Some(String::new()),
+            ..Dialect::unix()
+        }
+    }
+
+    /// `COPY` in PostgreSQL's default text format: tab separated, never quoted,
+    /// with `\` escaping tabs, line endings and itself, and `\N` for null
+    pub fn postgresql_text() -> Dialect {
+        Dialect {
+            delimiter: '\t',
+            text_enclosure: None,
+            escape: Some('\\'),
+            newline_type: NewlineType::UnixStyle,
+            null: Some("\\N".to_string())
+        }
+    }
+
+    /// `SELECT ... INTO OUTFILE` in MySQL with its default field and line options.
+    /// Laid out like PostgreSQL's text format, of which it is an alias
+    pub fn mysql_outfile() -> Dialect {
+        Dialect::postgresql_text()
+    }
+}
+
+impl<'a> From<&'a Dialect> for SimpleCsvReaderOptions {
+    fn from(dialect: &'a Dialect) -> SimpleCsvReaderOptions {
+        let terminator = match dialect.newline_type {
+            NewlineType::UnixStyle | NewlineType::WindowsStyle => RecordTerminator::Newline,
+            NewlineType::Custom(ref newline) => RecordTerminator::Custom(newline.clone())
+        };
+        SimpleCsvReaderOptions {
+            delimiter: dialect.delimiter,
+            text_enclosure: dialect.text_enclosure,
+            escape: dialect.escape,
+            terminator,
+            nulls: dialect.null.iter().cloned().collect(),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<Dialect> for SimpleCsvReaderOptions {
+    fn from(dialect: Dialect) -> SimpleCsvReaderOptions {
+        SimpleCsvReaderOptions::from(&dialect)
+    }
+}
+
+impl<'a> From<&'a Dialect> for SimpleCsvWriterOptions {
+    fn from(dialect: &'a Dialect) -> SimpleCsvWriterOptions {
+        SimpleCsvWriterOptions {
+            delimiter: dialect.delimiter,
+            text_enclosure: dialect.text_enclosure,
+            escape: dialect.escape,
+            newline_type: dialect.newline_type.clone(),
+            trailing_newline: true,
+            null: dialect.null.clone().unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<Dialect> for SimpleCsvWriterOptions {
+    fn from(dialect: Dialect) -> SimpleCsvWriterOptions {
+        SimpleCsvWriterOptions::from(&dialect)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::SimpleCsvReader;
+    use crate::writer::SimpleCsvWriter;
+
+    fn round_trip(dialect: &Dialect, input: &str) -> Vec<Vec<String>> {
+        let rows: Vec<Vec<String>> = SimpleCsvReader::with_options(input.as_bytes(),dialect.into())
+            .map(|row| row.unwrap())
+            .collect();
+        let mut writer = SimpleCsvWriter::with_options(Vec::new(),dialect.into());
+        writer.write_all(&rows).unwrap();
+        assert_eq!(String::from_utf8(writer.as_inner()).unwrap(), input, "{:?}", dialect);
+        rows
+    }
+
+    #[test]
+    fn dialect_round_trips() {
+        let rows = round_trip(&Dialect::rfc4180(),"id,note\r\n1,\"a, \"\"b\"\"\"\r\n2,\"c\r\nd\"\r\n3,\r\n");
+        assert_eq!(rows[1], vec!["1".to_string(),"a, \"b\"".to_string()]);
+        assert_eq!(rows[2][1], "c\r\nd");
+        round_trip(&Dialect::excel(),"a,b\r\n,\"\"\"\"\r\n");
+        round_trip(&Dialect::excel_tab(),"a\t\"b\tc\"\r\n1\t2,3\r\n");
+        round_trip(&Dialect::unix(),"a,\"b\nc\"\n");
+        round_trip(&Dialect::postgresql_csv(),"1,\"x,y\",\n");
+
+        let rows = round_trip(&Dialect::postgresql_text(),"1\ta\\tb\\nc\t\"q\" \\\\\n");
+        assert_eq!(rows[0], vec!["1".to_string(),"a\tb\nc".to_string(),"\"q\" \\".to_string()]);
+        round_trip(&Dialect::mysql_outfile(),"1\tC:\\\\dir\n2\t\\r\n");
+    }
+
+    #[test]
+    fn dialect_round_trips_nulls() {
+        let nullable = |dialect: &Dialect, input: &str| {
+            let mut reader = SimpleCsvReader::with_options(input.as_bytes(),dialect.into());
+            let mut writer = SimpleCsvWriter::with_options(Vec::new(),dialect.into());
+            let mut rows = Vec::new();
+            while let Some(row) = reader.next_nullable_row() {
+                let row = row.unwrap();
+                writer.write_nullable(row).unwrap();
+                rows.push(row.to_vec());
+            }
+            assert_eq!(String::from_utf8(writer.as_inner()).unwrap(), input, "{:?}", dialect);
+            rows
+        };
+        let rows = nullable(&Dialect::postgresql_csv(),"1,,\"\"\n");
+        assert_eq!(rows[0], vec![Some("1".to_string()),None,Some("".to_string())]);
+        let rows = nullable(&Dialect::postgresql_text(),"\\N\t\t\\\\N\tN\n");
+        assert_eq!(rows[0], vec![None,Some("".to_string()),Some("\\N".to_string()),Some("N".to_string())]);
+
+        // Formats without nulls read empty fields as empty strings
+        for dialect in &[Dialect::rfc4180(),Dialect::excel(),Dialect::excel_tab(),Dialect::unix()] {
+            let csv_options = SimpleCsvReaderOptions::from(dialect);
+            assert!(csv_options.nulls.is_empty());
+            let mut reader = SimpleCsvReader::with_options(&b"1,,\"\"\n"[..],csv_options);
+            assert!(reader.next_nullable_row().unwrap().unwrap().iter().all(|field| field.is_some()));
+        }
+    }
+}
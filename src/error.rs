@@ -0,0 +1,109 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+pub type Result<T> = result::Result<T,Error>;
+
+/// A location in the input.
+/// `byte` is the offset from the start of the input, `line` is the 1-based physical line
+/// and `record` is the 0-based index of the record, counting a header row if there is one.
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+pub struct Position {
+    pub byte: u64,
+    pub line: u64,
+    pub record: u64
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,"line {}, record {}, byte {}",self.line,self.record,self.byte)
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// Reading from the underlying input failed
+    Io { error: io::Error, pos: Position },
+    /// A field is not valid UTF-8. The position is the first invalid byte
+    Utf8 { pos: Position },
+    /// The input ended inside a quoted field
+    UnterminatedQuote { pos: Position },
+    /// A closing quote was followed by something other than a delimiter or newline
+    UnexpectedDataAfterQuote { pos: Position },
+    /// A quote appeared inside a field that did not start with one
+    UnexpectedQuote { pos: Position },
+    /// A `\r` outside a quoted field was not followed by `\n`
+    StrayCarriageReturn { pos: Position },
+    /// A record has a different number of fields than expected
+    FieldCount { pos: Position, expected: usize, found: usize }
+}
+
+impl Error {
+    pub fn position(&self) -> Position {
+        match *self {
+            Error::Io { pos, .. } |
+            Error::Utf8 { pos } |
+            Error::UnterminatedQuote { pos } |
+            Error::UnexpectedDataAfterQuote { pos } |
+            Error::UnexpectedQuote { pos } |
+            Error::StrayCarriageReturn { pos } |
+            Error::FieldCount { pos, .. } => pos
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io { ref error, pos } => write!(f,"I/O error at {}: {}",pos,error),
+            Error::Utf8 { pos } => write!(f,"invalid UTF-8 at {}",pos),
+            Error::UnterminatedQuote { pos } => write!(f,"unterminated quoted field at {}",pos),
+            Error::UnexpectedDataAfterQuote { pos } => write!(f,"unexpected data after closing quote at {}",pos),
+            Error::UnexpectedQuote { pos } => write!(f,"quote in unquoted field at {}",pos),
+            Error::StrayCarriageReturn { pos } => write!(f,"carriage return without newline at {}",pos),
+            Error::FieldCount { pos, expected, found } => write!(f,"expected {} fields but found {} at {}",expected,found,pos)
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io { ref error, .. } => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(error: Error) -> io::Error {
+        match error {
+            Error::Io { error, .. } => error,
+            _ => io::Error::new(io::ErrorKind::InvalidData,error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn error_position_and_display() {
+        let pos = Position { byte: 12, line: 3, record: 2 };
+        let error = Error::FieldCount { pos, expected: 3, found: 2 };
+        assert_eq!(error.position(), pos);
+        assert_eq!(error.to_string(), "expected 3 fields but found 2 at line 3, record 2, byte 12");
+    }
+
+    #[test]
+    fn error_into_io_error() {
+        let pos = Position::default();
+        let io_error: io::Error = Error::Io { error: io::Error::new(io::ErrorKind::BrokenPipe,"gone"), pos }.into();
+        assert_eq!(io_error.kind(), io::ErrorKind::BrokenPipe);
+        let io_error: io::Error = Error::UnterminatedQuote { pos }.into();
+        assert_eq!(io_error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::ops::Index;
+use std::slice::Iter;
+
+/// Column names taken from the first record of a file read with `has_headers` enabled.
+#[derive(Clone,Debug,PartialEq)]
+pub struct Headers {
+    names: Vec<String>,
+    indexes: HashMap<String,Vec<usize>>
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub enum HeaderError {
+    /// The reader was not configured with `has_headers`, so there are no names to look up
+    NoHeaders,
+    /// No header has the requested name
+    MissingColumn(String),
+    /// More than one header has the requested name, so a lookup by name is ambiguous
+    DuplicateColumn(String),
+    /// The header at this index is an empty string
+    BlankColumn(usize),
+    /// The header exists but the current row is too short to have a field for it
+    MissingField(String)
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HeaderError::NoHeaders => write!(f,"reader was not configured to read headers"),
+            HeaderError::MissingColumn(ref name) => write!(f,"no column named \"{}\"",name),
+            HeaderError::DuplicateColumn(ref name) => write!(f,"more than one column named \"{}\"",name),
+            HeaderError::BlankColumn(index) => write!(f,"column {} has an empty header",index),
+            HeaderError::MissingField(ref name) => write!(f,"row has no field for column \"{}\"",name)
+        }
+    }
+}
+
+impl error::Error for HeaderError {}
+
+impl Headers {
+    pub fn new(names: Vec<String>) -> Headers {
+        let mut indexes: HashMap<String,Vec<usize>> = HashMap::with_capacity(names.len());
+        for (index, name) in names.iter().enumerate() {
+            indexes.entry(name.clone()).or_default().push(index);
+        }
+        Headers {
+            names,
+            indexes
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn as_slice(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn iter(&self) -> Iter<'_, String> {
+        self.names.iter()
+    }
+
+    /// Returns the column index for `name`, failing if the name is absent or used more than once
+    pub fn index_of(&self, name: &str) -> Result<usize,HeaderError> {
+        match self.indexes.get(name) {
+            Some(indexes) if indexes.len() == 1 => Ok(indexes[0]),
+            Some(..) => Err(HeaderError::DuplicateColumn(name.to_string())),
+            None => Err(HeaderError::MissingColumn(name.to_string()))
+        }
+    }
+
+    /// Names that appear more than once, in order of their first appearance
+    pub fn duplicates(&self) -> Vec<&str> {
+        self.names.iter().enumerate()
+            .filter(|&(index, name)| {
+                let indexes = &self.indexes[name];
+                indexes.len() > 1 && indexes[0] == index
+            })
+            .map(|(_, name)| &**name)
+            .collect()
+    }
+
+    /// Indexes of columns whose header is an empty string
+    pub fn blanks(&self) -> Vec<usize> {
+        match self.indexes.get("") {
+            Some(indexes) => indexes.clone(),
+            None => Vec::new()
+        }
+    }
+
+    /// Checks that every column has a unique, non-empty name
+    pub fn validate(&self) -> Result<(),HeaderError> {
+        if let Some(&index) = self.blanks().first() {
+            return Err(HeaderError::BlankColumn(index));
+        }
+        if let Some(name) = self.duplicates().first() {
+            return Err(HeaderError::DuplicateColumn(name.to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl Index<usize> for Headers {
+    type Output = str;
+
+    fn index(&self, index: usize) -> &str {
+        &self.names[index]
+    }
+}
+
+/// A row returned by `SimpleCsvReader::next_named_row`.
+/// Fields can be looked up by position or, when headers were read, by column name.
+#[derive(Clone,Copy,Debug)]
+pub struct Row<'a> {
+    fields: &'a [String],
+    headers: Option<&'a Headers>
+}
+
+impl<'a> Row<'a> {
+    pub fn new(fields: &'a [String], headers: Option<&'a Headers>) -> Row<'a> {
+        Row {
+            fields,
+            headers
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn as_slice(&self) -> &'a [String] {
+        self.fields
+    }
+
+    pub fn iter(&self) -> Iter<'a, String> {
+        self.fields.iter()
+    }
+
+    pub fn headers(&self) -> Option<&'a Headers> {
+        self.headers
+    }
+
+    pub fn get(&self, index: usize) -> Option<&'a str> {
+        self.fields.get(index).map(|field| &**field)
+    }
+
+    pub fn get_by_name(&self, name: &str) -> Result<&'a str,HeaderError> {
+        let headers = match self.headers {
+            Some(headers) => headers,
+            None => return Err(HeaderError::NoHeaders)
+        };
+        let index = headers.index_of(name)?;
+        self.get(index).ok_or_else(|| HeaderError::MissingField(name.to_string()))
+    }
+}
+
+impl<'a> Index<usize> for Row<'a> {
+    type Output = str;
+
+    fn index(&self, index: usize) -> &str {
+        &self.fields[index]
+    }
+}
+
+impl<'a, 'b> Index<&'b str> for Row<'a> {
+    type Output = str;
+
+    /// Panics if `name` is not a unique column name or the row has no field for it
+    fn index(&self, name: &str) -> &str {
+        match self.get_by_name(name) {
+            Ok(field) => field,
+            Err(e) => panic!("{}",e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(names: &[&str]) -> Headers {
+        Headers::new(names.iter().map(|name| name.to_string()).collect())
+    }
+
+    #[test]
+    fn headers_index_of() {
+        let headers = headers(&["a","b","c"]);
+        assert_eq!(headers.index_of("b"), Ok(1));
+        assert_eq!(headers.index_of("d"), Err(HeaderError::MissingColumn("d".to_string())));
+        assert!(headers.validate().is_ok());
+    }
+
+    #[test]
+    fn headers_duplicate_and_blank_names() {
+        let headers = headers(&["a","","b","a","","b","a"]);
+        assert_eq!(headers.duplicates(), vec!["a","","b"]);
+        assert_eq!(headers.blanks(), vec![1,4]);
+        assert_eq!(headers.index_of("a"), Err(HeaderError::DuplicateColumn("a".to_string())));
+        assert_eq!(headers.validate(), Err(HeaderError::BlankColumn(1)));
+    }
+
+    #[test]
+    fn headers_row_lookup() {
+        let headers = headers(&["a","b","c"]);
+        let fields = vec!["1".to_string(),"2".to_string()];
+        let row = Row::new(&fields,Some(&headers));
+        assert_eq!(row.get_by_name("b"), Ok("2"));
+        assert_eq!(&row["a"], "1");
+        assert_eq!(&row[1], "2");
+        assert_eq!(row.get_by_name("c"), Err(HeaderError::MissingField("c".to_string())));
+        assert_eq!(Row::new(&fields,None).get_by_name("a"), Err(HeaderError::NoHeaders));
+    }
+}
@@ -3,16 +3,73 @@
 
 pub use reader::SimpleCsvReader;
 pub use reader::SimpleCsvReaderOptions;
+pub use reader::SimpleCsvReaderOptionsBuilder;
+pub use reader::Strictness;
+pub use reader::Trim;
+pub use reader::RecordTerminator;
+pub use reader::ColumnCount;
+pub use push_parser::SimpleCsvPushParser;
 
 pub use writer::SimpleCsvWriter;
 pub use writer::SimpleCsvWriterOptions;
+pub use writer::SimpleCsvWriterOptionsBuilder;
 pub use writer::NewlineType;
+pub use writer::UnquotedPolicy;
 
+pub use dialect::Dialect;
 
+pub use options::OptionsError;
+
+pub use error::{Error,Position,Result};
+
+pub use headers::Headers;
+pub use headers::HeaderError;
+pub use headers::Row;
+
+pub use record::{ByteRecord,StringRecord};
+
+pub use visitor::{FieldInfo,RecordInfo,Visitor};
+
+pub use parallel::{ParallelCsvReader,ParallelCsvOptions};
+
+pub use sniffer::{Replay,Sniffed,Sniffer};
+
+#[cfg(feature = "mmap")]
+pub use mmap::{BorrowedRecord,MmapInput};
+
+#[cfg(feature = "async")]
+pub use async_reader::AsyncSimpleCsvReader;
+#[cfg(feature = "async")]
+pub use async_writer::AsyncSimpleCsvWriter;
+
+#[cfg(feature = "serde")]
+pub use deserializer::{DeserializeError,DeserializeErrorKind,DeserializeRows};
+
+
+#[cfg(feature = "async")]
+pub mod async_reader;
+#[cfg(feature = "async")]
+pub mod async_writer;
+#[cfg(feature = "serde")]
+pub mod deserializer;
+pub mod dialect;
+pub mod error;
+pub mod headers;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+pub mod options;
+pub mod parallel;
+mod parser;
+pub mod push_parser;
 pub mod reader;
+pub mod record;
+mod scan;
+pub mod sniffer;
+pub mod visitor;
 pub mod writer;
 
 #[cfg(test)]
+#[allow(clippy::explicit_auto_deref,clippy::field_reassign_with_default)]
 mod tests {
     use std::default::Default;
     use super::*;
@@ -0,0 +1,178 @@
+use std::fs::File;
+use std::io::{self,BufRead,Read};
+use std::path::Path;
+use memmap2::Mmap;
+use crate::error::{Position,Result};
+use crate::reader::{SimpleCsvReader,SimpleCsvReaderOptions};
+use crate::record::ByteRecord;
+
+/// A memory-mapped file read through `BufRead`.
+///
+/// `fill_buf` returns all of the file that has not been consumed yet,
+/// so the parser scans the mapping without reading it into a buffer first.
+/// The fields of each row are still parsed into the reader's record, as with any other input;
+/// `SimpleCsvReader::next_borrowed_record` hands them out from the mapping where it can.
+pub struct MmapInput {
+    map: Mmap,
+    offset: usize
+}
+
+impl MmapInput {
+    /// Maps the file at `path`.
+    ///
+    /// # Safety
+    /// The file must not be modified or truncated while it is mapped, see `memmap2::Mmap::map`.
+    pub unsafe fn open<P: AsRef<Path>>(path: P) -> io::Result<MmapInput> {
+        let file = File::open(path)?;
+        let map = Mmap::map(&file)?;
+        Ok(MmapInput { map, offset: 0 })
+    }
+
+    /// The whole mapped file
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+impl Read for MmapInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = (&self.map[self.offset..]).read(buf)?;
+        self.offset += n;
+        Ok(n)
+    }
+}
+
+impl BufRead for MmapInput {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(&self.map[self.offset..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.offset = (self.offset + amt).min(self.map.len());
+    }
+}
+
+impl SimpleCsvReader<MmapInput> {
+    /// Reads the file at `path` through a memory map instead of a buffered reader.
+    ///
+    /// This saves the read into a buffer. The other ways of reading a row return the fields from the reader's record
+    /// as with any other input; `next_borrowed_record` returns them from the mapping where it can.
+    ///
+    /// # Safety
+    /// The file must not be modified or truncated while the reader exists, see `memmap2::Mmap::map`.
+    pub unsafe fn from_path_mmap<P: AsRef<Path>>(path: P, options: SimpleCsvReaderOptions) -> io::Result<SimpleCsvReader<MmapInput>> {
+        Ok(SimpleCsvReader::with_options(MmapInput::open(path)?,options))
+    }
+
+    /// Like `next_byte_record`, but fields that are in the file byte for byte, such as unquoted fields without escapes,
+    /// are slices of the mapping instead of the reader's record
+    pub fn next_borrowed_record(&mut self) -> Option<Result<BorrowedRecord<'_>>> {
+        if let Err(e) = self.next_byte_record()? {
+            return Some(Err(e));
+        }
+        let parser = self.push_parser();
+        Some(Ok(BorrowedRecord { map: self.input().as_bytes(), record: parser.byte_record(), starts: parser.field_positions() }))
+    }
+
+    /// The raw bytes of the most recently read row, borrowed from the mapping.
+    /// Quotes and the line ending are included as they appear in the file.
+    pub fn record_bytes(&self) -> &[u8] {
+        let range = self.byte_range();
+        &self.input().as_bytes()[range.start as usize..range.end as usize]
+    }
+}
+
+/// A row of a memory-mapped file, see `SimpleCsvReader::next_borrowed_record`
+#[derive(Clone,Copy)]
+pub struct BorrowedRecord<'a> {
+    map: &'a [u8],
+    record: &'a ByteRecord,
+    // Where each field starts in the file
+    starts: &'a [Position]
+}
+
+impl<'a> BorrowedRecord<'a> {
+    pub fn len(&self) -> usize {
+        self.record.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.record.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&'a [u8]> {
+        let field = self.record.get(index)?;
+        Some(self.in_map(index,field).unwrap_or(field))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'a [u8]> {
+        let record = *self;
+        (0..self.len()).map(move |index| record.get(index).unwrap())
+    }
+
+    /// Whether field `index` points into the mapping rather than the reader's record
+    pub fn is_borrowed(&self, index: usize) -> bool {
+        matches!(self.record.get(index), Some(field) if self.in_map(index,field).is_some())
+    }
+
+    // The bytes of the file where field `index` starts, if they are the field
+    fn in_map(&self, index: usize, field: &[u8]) -> Option<&'a [u8]> {
+        let start = self.starts[index].byte as usize;
+        self.map.get(start..start + field.len()).filter(|bytes| *bytes == field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::Trim;
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+
+    fn temp_file(name: &str, data: &[u8]) -> ::std::path::PathBuf {
+        let path = env::temp_dir().join(format!("simple_csv_{}_{}.csv",name,::std::process::id()));
+        File::create(&path).unwrap().write_all(data).unwrap();
+        path
+    }
+
+    #[test]
+    fn mmap_reader() {
+        let data = b"id,name\r\n1,\"a\r\nb\"\r\n2,\"c\"\"\"";
+        let path = temp_file("mmap",data);
+        let csv_options = SimpleCsvReaderOptions { has_headers: true, ..Default::default() };
+        let mut reader = unsafe { SimpleCsvReader::from_path_mmap(&path,csv_options).unwrap() };
+
+        assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["1".to_string(),"a\r\nb".to_string()]);
+        assert_eq!(reader.byte_range(), 9..19);
+        assert_eq!(reader.record_bytes(), b"1,\"a\r\nb\"\r\n");
+        assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["2".to_string(),"c\"".to_string()]);
+        assert_eq!(reader.record_bytes(), b"2,\"c\"\"\"");
+        assert!(reader.next_row().is_none());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn mmap_borrowed_record() {
+        let data = b"id,name\n1,plain,\"quoted\",\"a\"\"b\", padded\n";
+        let path = temp_file("mmap_borrowed",data);
+        let csv_options = SimpleCsvReaderOptions { has_headers: true, trim: Trim::Leading, ..Default::default() };
+        let mut reader = unsafe { SimpleCsvReader::from_path_mmap(&path,csv_options).unwrap() };
+
+        let record = reader.next_borrowed_record().unwrap().unwrap();
+        assert_eq!(record.iter().collect::<Vec<_>>(), vec![&b"1"[..],b"plain",b"quoted",b"a\"b",b"padded"]);
+        assert_eq!((0..record.len()).map(|index| record.is_borrowed(index)).collect::<Vec<_>>(), vec![true,true,false,false,false]);
+        let field = record.get(1).unwrap();
+        assert_eq!(field.as_ptr(), reader.input().as_bytes()[10..].as_ptr());
+        assert!(reader.next_borrowed_record().is_none());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn mmap_empty_file() {
+        let path = temp_file("mmap_empty",b"");
+        let mut reader = unsafe { SimpleCsvReader::from_path_mmap(&path,Default::default()).unwrap() };
+        assert!(reader.next_row().is_none());
+        fs::remove_file(&path).unwrap();
+    }
+}
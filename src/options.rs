@@ -0,0 +1,125 @@
+use std::error;
+use std::fmt;
+
+/// Why `SimpleCsvReaderOptionsBuilder` or `SimpleCsvWriterOptionsBuilder` refused a combination of options
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum OptionsError {
+    /// The option is set to an empty string
+    Empty(&'static str),
+    /// The option holds `\n` or `\r`, which only the record terminator may use
+    LineEnding(&'static str),
+    /// The two options could be mistaken for each other, because one starts with the other.
+    /// Holds the text they share
+    Conflict(&'static str, &'static str, String),
+    /// The first option is not set, but the second one needs it
+    Missing(&'static str, &'static str)
+}
+
+impl fmt::Display for OptionsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OptionsError::Empty(option) => write!(f,"{} is empty",option),
+            OptionsError::LineEnding(option) => write!(f,"{} must not be a line ending character",option),
+            OptionsError::Conflict(first, second, ref shared) => write!(f,"{} and {} must differ, but both use {:?}",first,second,shared),
+            OptionsError::Missing(option, needed_by) => write!(f,"{} must be set for {}",option,needed_by)
+        }
+    }
+}
+
+impl error::Error for OptionsError {}
+
+// Checks the characters and strings that give the input its structure, named after their options.
+// Only the terminator may hold line ending characters.
+pub(crate) fn check_literals(literals: &[(&'static str, String)], terminator: Option<(&'static str, String)>) -> Result<(), OptionsError> {
+    for &(option, ref text) in literals.iter() {
+        if text.is_empty() {
+            return Err(OptionsError::Empty(option));
+        }
+        if text.contains(['\n', '\r']) {
+            return Err(OptionsError::LineEnding(option));
+        }
+    }
+    let mut literals = literals.to_vec();
+    if let Some((option, terminator)) = terminator {
+        if terminator.is_empty() {
+            return Err(OptionsError::Empty(option));
+        }
+        literals.push((option,terminator));
+    }
+    for (index, &(first, ref a)) in literals.iter().enumerate() {
+        for &(second, ref b) in literals[index + 1..].iter() {
+            let shared = if a.starts_with(b.as_str()) { b } else if b.starts_with(a.as_str()) { a } else { continue };
+            return Err(OptionsError::Conflict(first,second,shared.clone()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{RecordTerminator,SimpleCsvReader,SimpleCsvReaderOptions};
+    use crate::writer::{NewlineType,SimpleCsvWriter,SimpleCsvWriterOptions,UnquotedPolicy};
+
+    #[test]
+    fn options_reader_builder() {
+        let options = SimpleCsvReaderOptions::builder()
+            .delimiter(';')
+            .comment(Some('#'))
+            .escape(Some('\\'))
+            .has_headers(true)
+            .build()
+            .unwrap();
+        assert_eq!(options.delimiter, ';');
+        assert_eq!(options.comment, Some('#'));
+        assert!(options.has_headers);
+
+        let error = SimpleCsvReaderOptions::builder().delimiter('"').build().err().unwrap();
+        assert_eq!(error, OptionsError::Conflict("delimiter","text_enclosure","\"".to_string()));
+        assert_eq!(error.to_string(), "delimiter and text_enclosure must differ, but both use \"\\\"\"");
+        assert_eq!(SimpleCsvReaderOptions::builder().delimiter('\n').build().err(), Some(OptionsError::LineEnding("delimiter")));
+        assert_eq!(SimpleCsvReaderOptions::builder().escape(Some('\r')).build().err(), Some(OptionsError::LineEnding("escape")));
+        assert_eq!(SimpleCsvReaderOptions::builder().text_enclosure(None).comment(Some(',')).build().err(),
+            Some(OptionsError::Conflict("delimiter","comment",",".to_string())));
+        assert_eq!(SimpleCsvReaderOptions::builder().delimiter_str("").build().err(), Some(OptionsError::Empty("delimiter_str")));
+        // Escaping the quote with itself is doubling it
+        assert!(SimpleCsvReaderOptions::builder().escape(Some('"')).build().is_ok());
+        assert_eq!(SimpleCsvReaderOptions::builder().escape(Some(',')).build().err(),
+            Some(OptionsError::Conflict("delimiter","escape",",".to_string())));
+
+        // Line endings are only for the terminator, which must not clash with the rest either
+        let options = SimpleCsvReaderOptions::builder().terminator(RecordTerminator::Custom("\r\n;".to_string())).build();
+        assert!(options.is_ok());
+        assert_eq!(SimpleCsvReaderOptions::builder().delimiter_str("||").terminator(RecordTerminator::Custom("|".to_string())).build().err(),
+            Some(OptionsError::Conflict("delimiter_str","terminator","|".to_string())));
+    }
+
+    #[test]
+    fn options_writer_builder() {
+        let options = SimpleCsvWriterOptions::builder()
+            .delimiter('\t')
+            .newline_type(NewlineType::WindowsStyle)
+            .trailing_newline(true)
+            .build()
+            .unwrap();
+        assert_eq!(options.delimiter, '\t');
+        assert_eq!(options.newline_type, NewlineType::WindowsStyle);
+
+        // Escaping the quote with itself is doubling it, and reads back with the same options
+        let options = SimpleCsvWriterOptions::builder().escape(Some('"')).build().unwrap();
+        let mut writer = SimpleCsvWriter::with_options(Vec::new(),options);
+        writer.write(&["a\"b".to_string(),"c".to_string()]).unwrap();
+        let bytes = writer.as_inner();
+        assert_eq!(bytes, b"\"a\"\"b\",c");
+        let csv_options = SimpleCsvReaderOptions::builder().escape(Some('"')).build().unwrap();
+        let row = SimpleCsvReader::with_options(&bytes[..],csv_options).next_row().unwrap().unwrap().to_vec();
+        assert_eq!(row, vec!["a\"b".to_string(),"c".to_string()]);
+        assert_eq!(SimpleCsvWriterOptions::builder().escape(Some(',')).build().err(),
+            Some(OptionsError::Conflict("delimiter","escape",",".to_string())));
+        assert_eq!(SimpleCsvWriterOptions::builder().text_enclosure(Some('\r')).build().err(), Some(OptionsError::LineEnding("text_enclosure")));
+        assert_eq!(SimpleCsvWriterOptions::builder().newline_type(NewlineType::Custom(",\n".to_string())).build().err(),
+            Some(OptionsError::Conflict("delimiter","newline_type",",".to_string())));
+        assert_eq!(SimpleCsvWriterOptions::builder().unquoted_policy(UnquotedPolicy::Escape).build().err(), Some(OptionsError::Missing("escape","unquoted_policy")));
+        assert!(SimpleCsvWriterOptions::builder().unquoted_policy(UnquotedPolicy::Escape).escape(Some('\\')).build().is_ok());
+    }
+}
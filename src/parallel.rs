@@ -0,0 +1,572 @@
+use std::collections::BTreeMap;
+use std::fs::{self,File};
+use std::io::{self,BufReader,Read,Seek,SeekFrom};
+use std::path::{Path,PathBuf};
+use std::sync::atomic::{AtomicBool,AtomicUsize,Ordering};
+use std::sync::{mpsc,Condvar,Mutex};
+use std::thread;
+use crate::error::{Error,Position,Result};
+use crate::reader::{ColumnCount,RecordTerminator,SimpleCsvReader,SimpleCsvReaderOptions};
+use crate::record::StringRecord;
+
+#[derive(Clone)]
+pub struct ParallelCsvOptions {
+    pub reader: SimpleCsvReaderOptions,
+    /// Number of worker threads. 0 starts one per available core
+    pub threads: usize,
+    /// Number of bytes each worker takes at a time, before moving the split to the next record boundary
+    pub chunk_size: usize
+}
+
+impl Default for ParallelCsvOptions {
+    fn default() -> ParallelCsvOptions {
+        ParallelCsvOptions {
+            reader: SimpleCsvReaderOptions::default(),
+            threads: 0,
+            chunk_size: 8 * 1024 * 1024
+        }
+    }
+}
+
+enum Source<'a> {
+    Slice(&'a [u8]),
+    File(PathBuf)
+}
+
+/// Parses a whole file or slice on several threads.
+///
+/// The input is cut into chunks of `chunk_size` bytes. A first pass over all chunks counts quotes and newlines,
+/// which tells where the first record of each chunk starts even when quoted fields contain newlines.
+/// The chunks are then parsed in parallel, each by a `SimpleCsvReader`, so records, errors and positions
+/// are exactly the same as reading the input from start to end.
+///
+/// Finding record boundaries this way relies on quotes only opening fields, closing them and being doubled inside them.
+/// If any quote outside a quoted field is found elsewhere, or `comment`, `escape` or a `terminator` other than a newline is set, the input is read by a single reader instead.
+pub struct ParallelCsvReader<'a> {
+    source: Source<'a>,
+    len: u64,
+    options: ParallelCsvOptions
+}
+
+// A range of the input that starts at a record boundary
+#[derive(Copy,Clone)]
+struct Range {
+    start: Position,
+    end: u64
+}
+
+impl<'a> ParallelCsvReader<'a> {
+    pub fn from_slice(data: &'a [u8], options: ParallelCsvOptions) -> ParallelCsvReader<'a> {
+        ParallelCsvReader {
+            source: Source::Slice(data),
+            len: data.len() as u64,
+            options
+        }
+    }
+
+    /// Reads the file at `path`. Every worker opens the file and reads the chunks it parses
+    pub fn from_path<P: AsRef<Path>>(path: P, options: ParallelCsvOptions) -> io::Result<ParallelCsvReader<'static>> {
+        let len = fs::metadata(path.as_ref())?.len();
+        Ok(ParallelCsvReader {
+            source: Source::File(path.as_ref().to_path_buf()),
+            len,
+            options
+        })
+    }
+
+    /// Calls `f` with every record in the order of the input, on the calling thread.
+    /// Returns an error if reading the input fails.
+    pub fn for_each<F>(&self, mut f: F) -> Result<()> where F: FnMut(Position,Result<&StringRecord>) {
+        let ranges = match self.find_ranges()? {
+            Some(ranges) => ranges,
+            None => return self.read_sequentially(f)
+        };
+        let threads = self.threads();
+        // At most this many parsed chunks wait to be handed to `f`
+        let window = threads * 2;
+        let next = AtomicUsize::new(0);
+        let stop = AtomicBool::new(false);
+        let delivered = Mutex::new(0usize);
+        let delivered_changed = Condvar::new();
+
+        thread::scope(|scope| {
+            let (sender, receiver) = mpsc::channel();
+            for _ in 0..threads {
+                let sender = sender.clone();
+                let (ranges, next, stop, delivered, delivered_changed) = (&ranges, &next, &stop, &delivered, &delivered_changed);
+                scope.spawn(move || {
+                    let mut input = RangeReader::new(&self.source);
+                    loop {
+                        let index = next.fetch_add(1,Ordering::SeqCst);
+                        if index >= ranges.len() {
+                            break;
+                        }
+                        let mut count = delivered.lock().unwrap();
+                        while index >= *count + window && !stop.load(Ordering::SeqCst) {
+                            count = delivered_changed.wait(count).unwrap();
+                        }
+                        drop(count);
+                        if stop.load(Ordering::SeqCst) {
+                            break;
+                        }
+                        // If parsing panics, say so, or the chunk would be waited for forever
+                        let panicked = OnUnwind(|| {
+                            let _ = sender.send((index,None));
+                        });
+                        let mut records = Vec::new();
+                        let result = self.parse_range(&mut input,ranges[index],|pos, record| {
+                            records.push((pos,record.cloned()));
+                        });
+                        drop(panicked);
+                        if sender.send((index,Some(result.map(|_| records)))).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+            // Wake the workers waiting for the window to move once nothing more will be handed over,
+            // including when `f` panics
+            let _stop = Stop { stop: &stop, delivered: &delivered, delivered_changed: &delivered_changed };
+
+            // Chunks finish in any order. Hold on to them until all earlier chunks have been handed over
+            let mut waiting = BTreeMap::new();
+            let mut next_index = 0;
+            let mut outcome = Ok(());
+            for (index, records) in receiver.iter() {
+                let records = match records {
+                    Some(records) => records,
+                    // The scope passes the worker's panic on once the others have stopped
+                    None => break
+                };
+                waiting.insert(index,records);
+                while let Some(records) = waiting.remove(&next_index) {
+                    match records {
+                        Ok(records) => {
+                            for (pos, record) in records {
+                                match record {
+                                    Ok(ref record) => f(pos,Ok(record)),
+                                    Err(e) => f(pos,Err(e))
+                                }
+                            }
+                        },
+                        Err(e) => {
+                            outcome = Err(e);
+                            break;
+                        }
+                    }
+                    next_index += 1;
+                    *delivered.lock().unwrap() = next_index;
+                    delivered_changed.notify_all();
+                }
+                if outcome.is_err() {
+                    break;
+                }
+            }
+            outcome
+        })
+    }
+
+    /// Calls `f` with every record as soon as it is parsed, on the worker threads.
+    /// Records of different chunks arrive in any order; `Position::record` gives the index of each one.
+    /// Returns an error if reading the input fails.
+    pub fn for_each_unordered<F>(&self, f: F) -> Result<()> where F: Fn(Position,Result<&StringRecord>) + Sync {
+        let ranges = match self.find_ranges()? {
+            Some(ranges) => ranges,
+            None => return self.read_sequentially(f)
+        };
+        let next = AtomicUsize::new(0);
+        let failure = Mutex::new(None);
+
+        thread::scope(|scope| {
+            for _ in 0..self.threads() {
+                let (ranges, next, failure, f) = (&ranges, &next, &failure, &f);
+                scope.spawn(move || {
+                    let mut input = RangeReader::new(&self.source);
+                    loop {
+                        let index = next.fetch_add(1,Ordering::SeqCst);
+                        if index >= ranges.len() {
+                            break;
+                        }
+                        if let Err(e) = self.parse_range(&mut input,ranges[index],f) {
+                            // Stop handing out chunks to every worker
+                            next.store(ranges.len(),Ordering::SeqCst);
+                            failure.lock().unwrap().get_or_insert(e);
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        match failure.into_inner().unwrap() {
+            Some(e) => Err(e),
+            None => Ok(())
+        }
+    }
+
+    fn threads(&self) -> usize {
+        if self.options.threads > 0 {
+            self.options.threads
+        } else {
+            thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+        }
+    }
+
+    fn parse_range<F>(&self, input: &mut RangeReader, range: Range, mut f: F) -> Result<()> where F: FnMut(Position,Result<&StringRecord>) {
+        let bytes = input.read(range.start.byte,range.end).map_err(|error| Error::Io { error, pos: range.start })?;
+        // Only the range at the start of the input has the header row
+        let options = SimpleCsvReaderOptions { has_headers: self.options.reader.has_headers && range.start.byte == 0, ..self.options.reader.clone() };
+        let mut reader = SimpleCsvReader::with_options(bytes,options);
+        reader.start_at(range.start);
+        while let Some((pos, record)) = reader.next_positioned_record() {
+            f(pos,record);
+        }
+        Ok(())
+    }
+
+    fn read_sequentially<F>(&self, mut f: F) -> Result<()> where F: FnMut(Position,Result<&StringRecord>) {
+        match self.source {
+            Source::Slice(data) => {
+                let mut reader = SimpleCsvReader::with_options(data,self.options.reader.clone());
+                while let Some((pos, record)) = reader.next_positioned_record() {
+                    f(pos,record);
+                }
+            },
+            Source::File(ref path) => {
+                let file = File::open(path).map_err(|error| Error::Io { error, pos: Position { line: 1, ..Position::default() } })?;
+                let mut reader = SimpleCsvReader::with_options(BufReader::new(file),self.options.reader.clone());
+                while let Some((pos, record)) = reader.next_positioned_record() {
+                    if let Err(e @ Error::Io { .. }) = record {
+                        return Err(e);
+                    }
+                    f(pos,record);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Splits the input into ranges that start at record boundaries.
+    // Returns None if a quote was found where the boundaries cannot be worked out from quote counts.
+    fn find_ranges(&self) -> Result<Option<Vec<Range>>> {
+        // Comment lines and escaped quotes may leave quotes that do not pair up,
+        // and records can only be counted by their newlines.
+        // Checking the column count needs the first record, which only the first chunk sees
+        let reader = &self.options.reader;
+        let newlines = reader.terminator == RecordTerminator::Newline || reader.terminator == RecordTerminator::Lf;
+        if reader.comment.is_some() || reader.escape.is_some() || !newlines || reader.column_count != ColumnCount::Flexible {
+            return Ok(None);
+        }
+        let delimiter = &reader.delimiter_bytes()[..];
+        // Without quoting every newline ends a record
+        let mut quote_bytes = [0u8; 4];
+        let quote = reader.text_enclosure.map_or(&[][..],|c| c.encode_utf8(&mut quote_bytes).as_bytes());
+        // Checking a quote looks at the bytes before it, and a quote may run past the end of a chunk
+        let context = delimiter.len().max(quote.len()) as u64;
+
+        let chunk_size = self.options.chunk_size.max(1) as u64;
+        let chunks = self.len.div_ceil(chunk_size) as usize;
+        let next = AtomicUsize::new(0);
+        let scans = Mutex::new(BTreeMap::new());
+        let failure = Mutex::new(None);
+        thread::scope(|scope| {
+            for _ in 0..self.threads().min(chunks) {
+                let (next, scans, failure) = (&next, &scans, &failure);
+                scope.spawn(move || {
+                    let mut input = RangeReader::new(&self.source);
+                    loop {
+                        let index = next.fetch_add(1,Ordering::SeqCst);
+                        if index >= chunks {
+                            break;
+                        }
+                        let start = index as u64 * chunk_size;
+                        let end = (start + chunk_size).min(self.len);
+                        let window_start = start.saturating_sub(context);
+                        let window_end = (end + context - 1).min(self.len);
+                        match input.read(window_start,window_end) {
+                            Ok(window) => {
+                                let scan = scan_chunk(window,(start - window_start) as usize,(end - window_start) as usize,window_start == 0,delimiter,quote);
+                                scans.lock().unwrap().insert(index,scan);
+                            },
+                            Err(error) => {
+                                next.store(chunks,Ordering::SeqCst);
+                                // Lines and records have not been counted yet
+                                failure.lock().unwrap().get_or_insert(Error::Io { error, pos: Position { byte: window_start, ..Position::default() } });
+                                break;
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        if let Some(e) = failure.into_inner().unwrap() {
+            return Err(e);
+        }
+
+        let mut starts = vec![Position { byte: 0, line: 1, record: 0 }];
+        let mut in_quotes = false;
+        let mut lines = 0;
+        let mut records = 0;
+        for (index, scan) in scans.into_inner().unwrap() {
+            let summary = scan.summaries[in_quotes as usize];
+            if !summary.valid {
+                return Ok(None);
+            }
+            if let Some((offset, newlines)) = summary.first_boundary {
+                if index > 0 {
+                    starts.push(Position {
+                        byte: index as u64 * chunk_size + offset,
+                        line: lines + newlines + 1,
+                        record: records + 1
+                    });
+                }
+            }
+            lines += scan.newlines;
+            records += summary.record_ends;
+            in_quotes ^= scan.odd_quotes;
+        }
+
+        let ends: Vec<u64> = starts.iter().skip(1).map(|start| start.byte).chain(Some(self.len)).collect();
+        Ok(Some(starts.into_iter().zip(ends)
+            .filter(|&(start, end)| start.byte < end)
+            .map(|(start, end)| Range { start, end })
+            .collect()))
+    }
+}
+
+// Tells the workers of `for_each` to stop when dropped
+struct Stop<'a> {
+    stop: &'a AtomicBool,
+    delivered: &'a Mutex<usize>,
+    delivered_changed: &'a Condvar
+}
+
+impl<'a> Drop for Stop<'a> {
+    fn drop(&mut self) {
+        // Holding the lock makes sure a worker is either waiting already or sees `stop` before it waits
+        let _count = self.delivered.lock().unwrap_or_else(|e| e.into_inner());
+        self.stop.store(true,Ordering::SeqCst);
+        self.delivered_changed.notify_all();
+    }
+}
+
+// Calls the function when dropped while the thread is panicking
+struct OnUnwind<F: FnMut()>(F);
+
+impl<F: FnMut()> Drop for OnUnwind<F> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            (self.0)();
+        }
+    }
+}
+
+// Reads ranges of the input, borrowing them from a slice or reading them from a file handle of its own
+struct RangeReader<'s, 'a: 's> {
+    source: &'s Source<'a>,
+    file: Option<File>,
+    buffer: Vec<u8>
+}
+
+impl<'s, 'a: 's> RangeReader<'s, 'a> {
+    fn new(source: &'s Source<'a>) -> RangeReader<'s, 'a> {
+        RangeReader {
+            source,
+            file: None,
+            buffer: Vec::new()
+        }
+    }
+
+    fn read(&mut self, start: u64, end: u64) -> io::Result<&[u8]> {
+        match *self.source {
+            Source::Slice(data) => Ok(&data[start as usize..end as usize]),
+            Source::File(ref path) => {
+                if self.file.is_none() {
+                    self.file = Some(File::open(path)?);
+                }
+                let file = self.file.as_mut().unwrap();
+                file.seek(SeekFrom::Start(start))?;
+                self.buffer.clear();
+                file.by_ref().take(end - start).read_to_end(&mut self.buffer)?;
+                if (self.buffer.len() as u64) < end - start {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof,"file is shorter than when it was opened"));
+                }
+                Ok(&self.buffer)
+            }
+        }
+    }
+}
+
+// What a chunk looks like under one assumption about whether it starts inside a quoted field
+#[derive(Copy,Clone)]
+struct Summary {
+    // Every quote outside a quoted field is at the start of a field
+    valid: bool,
+    // Offset just past the first newline outside quotes, and the number of newlines up to and including it
+    first_boundary: Option<(u64,u64)>,
+    // Newlines outside quotes. Each one ends a record
+    record_ends: u64
+}
+
+struct ChunkScan {
+    // An odd number of quotes flips whether the next chunk starts inside quotes
+    odd_quotes: bool,
+    newlines: u64,
+    // Indexed by whether the chunk starts inside quotes
+    summaries: [Summary; 2]
+}
+
+// Counts the quotes and newlines in window[start..end].
+// `window` has a few bytes of context on both sides, and starts at the beginning of the input if `at_input_start` is set.
+fn scan_chunk(window: &[u8], start: usize, end: usize, at_input_start: bool, delimiter: &[u8], quote: &[u8]) -> ChunkScan {
+    let summary = Summary {
+        valid: true,
+        first_boundary: None,
+        record_ends: 0
+    };
+    let mut scan = ChunkScan {
+        odd_quotes: false,
+        newlines: 0,
+        summaries: [summary, summary]
+    };
+    let mut offset = start;
+    let quote_start = quote.first().cloned();
+    while let Some(found) = window[offset..end].iter().position(|&b| b == b'\n' || Some(b) == quote_start) {
+        offset += found;
+        if window[offset] == b'\n' {
+            scan.newlines += 1;
+            for (starts_in_quotes, summary) in scan.summaries.iter_mut().enumerate() {
+                // Outside quotes if the quotes seen so far cancel out the starting state
+                if scan.odd_quotes == (starts_in_quotes == 1) {
+                    summary.record_ends += 1;
+                    if summary.first_boundary.is_none() {
+                        summary.first_boundary = Some(((offset + 1 - start) as u64,scan.newlines));
+                    }
+                }
+            }
+            offset += 1;
+        } else if !quote.is_empty() && window[offset..].starts_with(quote) {
+            // A quote outside a quoted field is only an opening quote at the start of a field.
+            // Right after another quote, it is the second half of an escaped quote.
+            let before = &window[..offset];
+            let at_field_start = (before.is_empty() && at_input_start) || before.ends_with(delimiter) || before.ends_with(b"\n") || before.ends_with(quote);
+            for (starts_in_quotes, summary) in scan.summaries.iter_mut().enumerate() {
+                if scan.odd_quotes == (starts_in_quotes == 1) && !at_field_start {
+                    summary.valid = false;
+                }
+            }
+            scan.odd_quotes = !scan.odd_quotes;
+            offset += quote.len();
+        } else {
+            offset += 1;
+        }
+    }
+    scan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::io::Write;
+    use std::panic;
+
+    fn sequential(data: &[u8], options: SimpleCsvReaderOptions) -> Vec<(Position,Vec<String>)> {
+        let mut reader = SimpleCsvReader::with_options(data,options);
+        let mut records = Vec::new();
+        while let Some((pos, record)) = reader.next_positioned_record() {
+            records.push((pos,record.unwrap().iter().map(|field| field.to_string()).collect()));
+        }
+        records
+    }
+
+    fn ordered(reader: &ParallelCsvReader) -> Vec<(Position,Vec<String>)> {
+        let mut records = Vec::new();
+        reader.for_each(|pos, record| {
+            records.push((pos,record.unwrap().iter().map(|field| field.to_string()).collect()));
+        }).unwrap();
+        records
+    }
+
+    fn test_input() -> Vec<u8> {
+        let mut input = String::new();
+        for i in 0..200 {
+            input.push_str(&format!("{},\"multi\r\nline {}\",\"\"\"quoted\"\"\",plain\r\n",i,i));
+            if i % 7 == 0 {
+                input.push_str("\r\n\"a,b\"\n");
+            }
+        }
+        input.push_str("last,\"row");
+        input.into_bytes()
+    }
+
+    #[test]
+    fn parallel_matches_sequential_reader() {
+        let input = test_input();
+        let expected = sequential(&input,SimpleCsvReaderOptions::default());
+        for &chunk_size in &[1,7,64,1000,100000] {
+            let options = ParallelCsvOptions { threads: 3, chunk_size, ..Default::default() };
+            let reader = ParallelCsvReader::from_slice(&input,options);
+            assert!(reader.find_ranges().unwrap().is_some());
+            assert_eq!(ordered(&reader), expected);
+
+            let records = Mutex::new(Vec::new());
+            reader.for_each_unordered(|pos, record| {
+                records.lock().unwrap().push((pos,record.unwrap().iter().map(|field| field.to_string()).collect()));
+            }).unwrap();
+            let mut records = records.into_inner().unwrap();
+            records.sort_by_key(|&(pos, _)| pos.record);
+            assert_eq!(records, expected);
+        }
+    }
+
+    #[test]
+    fn parallel_panic_in_callback_does_not_hang() {
+        let input = test_input();
+        let options = ParallelCsvOptions { threads: 2, chunk_size: 7, ..Default::default() };
+        let reader = ParallelCsvReader::from_slice(&input,options);
+        assert!(reader.find_ranges().unwrap().unwrap().len() > 2 * 2);
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            reader.for_each(|pos, _| {
+                if pos.record == 3 {
+                    panic!("stop here");
+                }
+            })
+        }));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn parallel_headers_and_utf8_delimiter() {
+        let input = "a\u{00A9}b\r\n1\u{00A9}\"\u{00A9}\n\"\r\n3\u{00A9}4\r\n".repeat(50).into_bytes();
+        let reader_options = SimpleCsvReaderOptions { delimiter: '\u{00A9}', has_headers: true, ..Default::default() };
+        let expected = sequential(&input,reader_options.clone());
+        assert_eq!(expected[0].0.record, 1);
+        let options = ParallelCsvOptions { reader: reader_options, threads: 4, chunk_size: 5 };
+        assert_eq!(ordered(&ParallelCsvReader::from_slice(&input,options)), expected);
+    }
+
+    #[test]
+    fn parallel_falls_back_on_stray_quotes() {
+        // The quote in 2"3 is data, so counting quotes would put the record boundaries in the wrong place
+        let input = "1,2\"3\n4,\"5\n6\"\n7,8\n".repeat(20).into_bytes();
+        let options = ParallelCsvOptions { threads: 2, chunk_size: 4, ..Default::default() };
+        let reader = ParallelCsvReader::from_slice(&input,options);
+        assert!(reader.find_ranges().unwrap().is_none());
+        assert_eq!(ordered(&reader), sequential(&input,SimpleCsvReaderOptions::default()));
+    }
+
+    #[test]
+    fn parallel_from_path() {
+        let input = test_input();
+        let path = env::temp_dir().join(format!("simple_csv_parallel_{}.csv",std::process::id()));
+        File::create(&path).unwrap().write_all(&input).unwrap();
+
+        let options = ParallelCsvOptions { threads: 2, chunk_size: 100, ..Default::default() };
+        let reader = ParallelCsvReader::from_path(&path,options).unwrap();
+        let records = ordered(&reader);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(records, sequential(&input,SimpleCsvReaderOptions::default()));
+    }
+}
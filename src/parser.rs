@@ -0,0 +1,864 @@
+use std::mem::take;
+use crate::error::{Error,Position};
+use crate::reader::{RecordTerminator,SimpleCsvReaderOptions,Strictness,Trim};
+use crate::record::ByteRecord;
+use crate::writer::NewlineType;
+use crate::scan::Scanner;
+
+#[derive(Clone,Copy,PartialEq,Eq)]
+enum ParseState {
+    Neutral,
+    InField,
+    InQuotedField,
+    EncounteredQuoteInQuotedField,
+    // Skipping a comment line up to its newline
+    Comment
+}
+
+// What the bytes at the current offset of the input represent
+enum Token {
+    Quote,
+    Delimiter,
+    Newline,
+    Return { before_newline: bool },
+    // The escape character and the byte after it, which is taken as data
+    Escaped(u8),
+    Byte(u8)
+}
+
+// What a byte may start, so that `Parser::token` can tell with a single lookup
+#[derive(Clone,Copy,PartialEq,Eq)]
+enum ByteClass {
+    Data,
+    Quote,
+    Delimiter,
+    Newline,
+    Return,
+    // A custom terminator, an escape, or the start of more than one kind of token
+    Special
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub(crate) enum ParseResult {
+    /// All of the input was used without completing a record. Call `parse` again with more input.
+    InputEmpty,
+    /// A record was completed. Any unused input should be passed to the next call to `parse`.
+    Record,
+    /// A field was completed part way through a record, which is only returned when `stop_at_fields` is set.
+    /// The record holds no other field. Any unused input should be passed to the next call to `parse`.
+    Field
+}
+
+/// Byte oriented CSV state machine, fed slices of input of any size
+pub(crate) struct Parser {
+    state: ParseState,
+    delimiter: Vec<u8>,
+    // A custom record terminator. None means a newline, optionally preceded by a return
+    terminator: Option<Vec<u8>>,
+    // Whether a return on its own also ends a record, when there is no custom terminator
+    any_newline: bool,
+    // The line ending of the first record that had one
+    newline_type: Option<NewlineType>,
+    classes: [ByteClass; 256],
+    // Empty when quoting is disabled
+    text_enclosure: Vec<u8>,
+    comment: Option<Vec<u8>>,
+    escape: Option<Vec<u8>>,
+    strictness: Strictness,
+    trim: Trim,
+    trim_leading: bool,
+    trim_trailing: bool,
+    // The whitespace that is trimmed: spaces and tabs, unless one of them is the delimiter
+    whitespace: Vec<u8>,
+    scanner: Scanner,
+    // Input that was accepted but not parsed yet because it may be the start of a token
+    pending: Vec<u8>,
+    // Set once a record has been returned; the next byte starts a new record
+    record_done: bool,
+    // Return each field as soon as it is complete, see `ParseResult::Field`
+    stop_at_fields: bool,
+    in_record: bool,
+    bytes: u64,
+    lines: u64,
+    records: u64,
+    record_position: Position,
+    field_position: Position,
+    field_positions: Vec<Position>,
+    // Whether the current field and each completed field of the record started with a quote
+    quoted: bool,
+    field_quoted: Vec<bool>,
+    // Indexes of the fields of the record that held an escape
+    escaped_fields: Vec<usize>,
+    track_utf8: bool,
+    // Field index, offset in the field and position of each piece of data that is not ASCII
+    utf8_marks: Vec<(usize,usize,Position)>,
+    quote_position: Position,
+    warnings: Vec<Error>,
+    error: Option<Error>,
+    comment_line: Vec<u8>,
+    comments: Vec<String>
+}
+
+impl Parser {
+    pub(crate) fn new(options: &SimpleCsvReaderOptions) -> Parser {
+        let delimiter = options.delimiter_bytes();
+        let terminator = match options.terminator {
+            RecordTerminator::Lf => Some(b"\n".to_vec()),
+            RecordTerminator::CrLf => Some(b"\r\n".to_vec()),
+            RecordTerminator::Cr => Some(b"\r".to_vec()),
+            RecordTerminator::Custom(ref terminator) if !terminator.is_empty() => Some(terminator.as_bytes().to_vec()),
+            _ => None
+        };
+        let any_newline = terminator.is_none() && options.terminator == RecordTerminator::Any;
+        let text_enclosure = options.text_enclosure.map_or(Vec::new(),|c| {
+            let mut text_enclosure_bytes = [0u8; 4];
+            c.encode_utf8(&mut text_enclosure_bytes).as_bytes().to_vec()
+        });
+
+        let comment = options.comment.map(|c| {
+            let mut comment_bytes = [0u8; 4];
+            c.encode_utf8(&mut comment_bytes).as_bytes().to_vec()
+        });
+
+        // Escaping the quote with itself is what doubled quotes already do
+        let escape = options.escape.filter(|&c| Some(c) != options.text_enclosure).map(|c| {
+            let mut escape_bytes = [0u8; 4];
+            c.encode_utf8(&mut escape_bytes).as_bytes().to_vec()
+        });
+
+        let whitespace = [b' ', b'\t'].iter().cloned().filter(|&b| delimiter != [b]).collect();
+
+        let line_start = match terminator {
+            Some(ref terminator) => vec![terminator[0]],
+            None => vec![b'\n',b'\r']
+        };
+        // Line endings inside quotes are data, but their lines are counted
+        let quoted_line_start = if any_newline { &line_start[..] } else { &line_start[..1] };
+        let scanner = Scanner::new(delimiter[0],text_enclosure.first().cloned(),&line_start,quoted_line_start,escape.as_ref().map(|escape| escape[0]));
+
+        let mut classes = [ByteClass::Data; 256];
+        let mut classify = |b: u8, class: ByteClass| {
+            let entry = &mut classes[b as usize];
+            *entry = if *entry == ByteClass::Data { class } else { ByteClass::Special };
+        };
+        if let Some(&quote) = text_enclosure.first() {
+            classify(quote,ByteClass::Quote);
+        }
+        classify(delimiter[0],ByteClass::Delimiter);
+        match terminator {
+            Some(ref terminator) => classify(terminator[0],ByteClass::Special),
+            None => {
+                classify(b'\n',ByteClass::Newline);
+                classify(b'\r',if any_newline { ByteClass::Special } else { ByteClass::Return });
+            }
+        }
+        if let Some(ref escape) = escape {
+            classify(escape[0],ByteClass::Special);
+        }
+
+        Parser {
+            state: ParseState::Neutral,
+            delimiter,
+            terminator,
+            any_newline,
+            newline_type: None,
+            classes,
+            text_enclosure,
+            comment,
+            escape,
+            strictness: options.strictness,
+            trim: options.trim,
+            trim_leading: options.trim != Trim::None && options.trim != Trim::Trailing,
+            trim_trailing: options.trim != Trim::None && options.trim != Trim::Leading,
+            whitespace,
+            scanner,
+            pending: Vec::new(),
+            record_done: true,
+            stop_at_fields: false,
+            in_record: false,
+            bytes: 0,
+            lines: 0,
+            records: 0,
+            record_position: Position::default(),
+            field_position: Position::default(),
+            field_positions: Vec::new(),
+            quoted: false,
+            field_quoted: Vec::new(),
+            escaped_fields: Vec::new(),
+            track_utf8: options.strictness != Strictness::Lenient,
+            utf8_marks: Vec::new(),
+            quote_position: Position::default(),
+            warnings: Vec::new(),
+            error: None,
+            comment_line: Vec::new(),
+            comments: Vec::new()
+        }
+    }
+
+    /// Position of the next byte to be parsed
+    pub(crate) fn position(&self) -> Position {
+        Position {
+            byte: self.bytes,
+            line: self.lines + 1,
+            record: self.records
+        }
+    }
+
+    /// Makes positions count from `pos`, for input that starts part way through a larger file
+    pub(crate) fn start_at(&mut self, pos: Position) {
+        self.bytes = pos.byte;
+        self.lines = pos.line - 1;
+        self.records = pos.record;
+    }
+
+    /// Position of the start of the current or last completed record
+    pub(crate) fn record_position(&self) -> Position {
+        self.record_position
+    }
+
+    /// Start positions of the fields of the current record
+    pub(crate) fn field_positions(&self) -> &[Position] {
+        &self.field_positions
+    }
+
+    /// Whether each field of the current record was enclosed in quotes
+    pub(crate) fn field_quoted(&self) -> &[bool] {
+        &self.field_quoted
+    }
+
+    /// Indexes of the fields of the current record that held an escape, in order
+    pub(crate) fn escaped_fields(&self) -> &[usize] {
+        &self.escaped_fields
+    }
+
+    /// Position in the input of byte `offset` of field `index` of the current record, if it is not ASCII
+    pub(crate) fn data_position(&self, index: usize, offset: usize) -> Option<Position> {
+        let &(_, start, pos) = self.utf8_marks.iter().rev().find(|&&(field, start, _)| field == index && start <= offset)?;
+        Some(Position { byte: pos.byte + (offset - start) as u64, ..pos })
+    }
+
+    /// Makes `parse` and `finish` return each field as soon as it is complete
+    pub(crate) fn set_stop_at_fields(&mut self, stop: bool) {
+        self.stop_at_fields = stop;
+    }
+
+    /// Whether the current record has failed
+    pub(crate) fn failed(&self) -> bool {
+        self.error.is_some()
+    }
+
+    /// Violations found in the current record when strictness is `Warn`
+    pub(crate) fn warnings(&self) -> &[Error] {
+        &self.warnings
+    }
+
+    /// The line ending of the first record that ended with one
+    pub(crate) fn newline_type(&self) -> Option<&NewlineType> {
+        self.newline_type.as_ref()
+    }
+
+    /// Comment lines skipped before the current record, without the comment character and line ending
+    pub(crate) fn comments(&self) -> &[String] {
+        &self.comments
+    }
+
+    /// The first violation found in the current record when strictness is `Strict`
+    pub(crate) fn take_error(&mut self) -> Option<Error> {
+        self.error.take()
+    }
+
+    // Records a departure from RFC 4180 according to the strictness
+    pub(crate) fn violation(&mut self, error: Error) {
+        match self.strictness {
+            Strictness::Lenient => {},
+            Strictness::Warn => {
+                self.warnings.push(error);
+            },
+            Strictness::Strict => {
+                if self.error.is_none() {
+                    self.error = Some(error);
+                }
+            }
+        }
+    }
+
+    // Fails the current record whatever the strictness, unless it already failed
+    pub(crate) fn fail(&mut self, error: Error) {
+        if self.error.is_none() {
+            self.error = Some(error);
+        }
+    }
+
+    /// Cuts the completed record down to `len` fields or pads it with empty ones
+    pub(crate) fn resize_record(&mut self, record: &mut ByteRecord, len: usize) {
+        record.truncate(len);
+        self.field_positions.truncate(len);
+        self.field_quoted.truncate(len);
+        let pos = self.field_positions.last().cloned().unwrap_or(self.record_position);
+        while self.field_positions.len() < len {
+            record.end_field();
+            self.field_positions.push(pos);
+            self.field_quoted.push(false);
+        }
+    }
+
+    fn start_record(&mut self, record: &mut ByteRecord) {
+        if self.record_done {
+            self.record_done = false;
+            record.clear();
+            self.field_positions.clear();
+            self.field_quoted.clear();
+            self.escaped_fields.clear();
+            self.utf8_marks.clear();
+            self.warnings.clear();
+            self.comments.clear();
+            self.error = None;
+            self.record_position = self.position();
+            self.field_position = self.record_position;
+        }
+    }
+
+    /// Parses as much of `input` as needed to complete a record.
+    /// Returns the number of bytes of `input` that were used.
+    pub(crate) fn parse(&mut self, input: &[u8], record: &mut ByteRecord) -> (usize, ParseResult) {
+        self.start_record(record);
+        let mut used = 0;
+        while !self.pending.is_empty() {
+            if used == input.len() {
+                return (used, ParseResult::InputEmpty);
+            }
+            // Finish the token that was split across inputs
+            let comment_len = self.comment.as_ref().map_or(0,|comment| comment.len());
+            // An escape is only complete with the byte after it
+            let escape_len = self.escape.as_ref().map_or(0,|escape| escape.len() + 1);
+            let terminator_len = self.terminator.as_ref().map_or(2,|terminator| terminator.len());
+            let len = (input.len() - used).min(self.delimiter.len().max(self.text_enclosure.len()).max(comment_len).max(escape_len).max(terminator_len));
+            let mut joined = take(&mut self.pending);
+            joined.extend_from_slice(&input[used..used + len]);
+            used += len;
+            let (parsed, result) = self.scan(&joined, record, false);
+            self.pending.extend_from_slice(&joined[parsed..]);
+            if result != ParseResult::InputEmpty {
+                return (used, result);
+            }
+        }
+        // Carry on with the rest of the input
+        let input = &input[used..];
+        let (parsed, result) = self.scan(input, record, false);
+        if result != ParseResult::InputEmpty {
+            return (used + parsed, result);
+        }
+        self.pending.extend_from_slice(&input[parsed..]);
+        (used + input.len(), result)
+    }
+
+    /// Signals the end of the input. After `Field` call `finish` again
+    pub(crate) fn finish(&mut self, record: &mut ByteRecord) -> ParseResult {
+        self.start_record(record);
+        if !self.pending.is_empty() {
+            let pending = take(&mut self.pending);
+            let (parsed, result) = self.scan(&pending, record, true);
+            self.pending.extend_from_slice(&pending[parsed..]);
+            if result != ParseResult::InputEmpty {
+                return result;
+            }
+        }
+        if self.state == ParseState::Comment {
+            // The input ended on a comment line
+            self.end_comment();
+            self.in_record = false;
+        }
+        if !self.in_record {
+            return ParseResult::InputEmpty;
+        }
+        if self.state == ParseState::InQuotedField {
+            let pos = self.quote_position;
+            self.violation(Error::UnterminatedQuote { pos });
+        }
+        // Keep a last field without a newline if it has data, was quoted or follows a delimiter
+        if record.partial_len() > 0 || !self.field_positions.is_empty() || self.state != ParseState::Neutral {
+            self.end_field(record);
+        }
+        self.end_record();
+        ParseResult::Record
+    }
+
+    // Notes that the current field holds an escape
+    #[inline(never)]
+    fn escaped_field(&mut self) {
+        let index = self.field_positions.len();
+        if self.escaped_fields.last() != Some(&index) {
+            self.escaped_fields.push(index);
+        }
+    }
+
+    // Notes where data that is not ASCII starts in the input
+    #[inline]
+    fn note_data(&mut self, record: &ByteRecord, data: &[u8], byte: u64) {
+        if self.track_utf8 && !data.is_ascii() {
+            self.note_utf8(record.partial_len(),byte);
+        }
+    }
+
+    #[inline(never)]
+    fn note_utf8(&mut self, offset: usize, byte: u64) {
+        let pos = Position { byte, line: self.lines + 1, record: self.records };
+        self.utf8_marks.push((self.field_positions.len(),offset,pos));
+    }
+
+    #[inline]
+    fn end_field(&mut self, record: &mut ByteRecord) {
+        if self.trim != Trim::None {
+            self.trim_field(record);
+        }
+        record.end_field();
+        self.field_positions.push(self.field_position);
+        self.field_quoted.push(self.quoted);
+        self.quoted = false;
+        self.field_position = self.position();
+        self.state = ParseState::Neutral;
+    }
+
+    #[inline(never)]
+    fn leading_whitespace(&self, input: &[u8]) -> usize {
+        input.iter().take_while(|b| self.whitespace.contains(b)).count()
+    }
+
+    // Trims what leading_whitespace could not skip
+    #[inline(never)]
+    fn trim_field(&mut self, record: &mut ByteRecord) {
+        let whitespace = &self.whitespace;
+        // A quoted field followed by data ends like an unquoted one
+        if self.quoted && self.state != ParseState::InField {
+            if self.trim == Trim::Both {
+                let leading = record.partial().iter().take_while(|b| whitespace.contains(b)).count();
+                record.trim_partial(true,true,|b| whitespace.contains(&b));
+                // Shift the marks of data that moved
+                let field = self.field_positions.len();
+                for mark in self.utf8_marks.iter_mut().filter(|mark| mark.0 == field) {
+                    let skipped = leading.saturating_sub(mark.1);
+                    mark.1 -= leading - skipped;
+                    mark.2.byte += skipped as u64;
+                }
+            }
+        } else if self.trim_trailing {
+            record.trim_partial(false,true,|b| whitespace.contains(&b));
+        }
+    }
+
+    fn end_comment(&mut self) {
+        if self.terminator.is_none() && self.comment_line.last() == Some(&b'\r') {
+            self.comment_line.pop();
+        }
+        self.comments.push(String::from_utf8_lossy(&self.comment_line).into_owned());
+        self.comment_line.clear();
+        self.state = ParseState::Neutral;
+        // The record starts after the comment
+        self.record_position = self.position();
+        self.field_position = self.record_position;
+    }
+
+    fn end_record(&mut self) {
+        self.quoted = false;
+        self.records += 1;
+        self.record_done = true;
+        self.in_record = false;
+        self.state = ParseState::Neutral;
+    }
+
+    // The token at the start of `rest`, or None if it may continue in the next input
+    #[inline]
+    fn token(&self, rest: &[u8], eof: bool) -> Option<(Token, usize)> {
+        let b = rest[0];
+        match self.classes[b as usize] {
+            ByteClass::Data => Some((Token::Byte(b), 1)),
+            ByteClass::Quote => match literal_len(rest, &self.text_enclosure, eof)? {
+                0 => Some((Token::Byte(b), 1)),
+                len => Some((Token::Quote, len))
+            },
+            ByteClass::Delimiter => match literal_len(rest, &self.delimiter, eof)? {
+                0 => Some((Token::Byte(b), 1)),
+                len => Some((Token::Delimiter, len))
+            },
+            ByteClass::Newline => Some((Token::Newline, 1)),
+            ByteClass::Return => return_token(rest, eof),
+            ByteClass::Special => self.special_token(rest, eof)
+        }
+    }
+
+    // Like `token`, for bytes that may start more than one kind of token
+    #[inline(never)]
+    fn special_token(&self, rest: &[u8], eof: bool) -> Option<(Token, usize)> {
+        let b = rest[0];
+        // The terminator goes first, as it may start like the delimiter, as `|\n` does `|`
+        if let Some(ref terminator) = self.terminator {
+            match literal_len(rest, terminator, eof) {
+                Some(0) => {},
+                Some(len) => return Some((Token::Newline, len)),
+                None => return None
+            }
+        }
+        match literal_len(rest, &self.text_enclosure, eof) {
+            Some(0) => {},
+            Some(len) => return Some((Token::Quote, len)),
+            None => return None
+        }
+        match literal_len(rest, &self.delimiter, eof) {
+            Some(0) => {},
+            Some(len) => return Some((Token::Delimiter, len)),
+            None => return None
+        }
+        if let Some(ref escape) = self.escape {
+            if b == escape[0] {
+                match rest.get(escape.len()) {
+                    Some(&escaped) if rest.starts_with(escape) => return Some((Token::Escaped(unescape(escaped)), escape.len() + 1)),
+                    None if !eof && escape.starts_with(rest) => return None,
+                    // An escape character at the very end of the input is kept as data
+                    _ => {}
+                }
+            }
+        }
+        match b {
+            b'\n' if self.terminator.is_none() => Some((Token::Newline, 1)),
+            b'\r' if self.terminator.is_none() => match return_token(rest, eof)? {
+                (Token::Return { before_newline: false }, len) if self.any_newline => Some((Token::Newline, len)),
+                token => Some(token)
+            },
+            _ => Some((Token::Byte(b), 1))
+        }
+    }
+
+    // Skips comment lines at the start of a record and returns the offset after them
+    #[inline(never)]
+    fn skip_comments(&mut self, input: &[u8], eof: bool) -> (usize, bool) {
+        let base = self.bytes;
+        let mut offset = 0usize;
+        while offset < input.len() {
+            let rest = &input[offset..];
+            if self.state != ParseState::Comment {
+                let comment = match self.comment {
+                    Some(ref comment) => comment,
+                    None => break
+                };
+                // Whitespace before the comment character makes the line a record
+                if base + offset as u64 != self.record_position.byte || !rest.starts_with(&comment[..1]) {
+                    break;
+                }
+                if !rest.starts_with(comment) {
+                    if !eof && comment.starts_with(rest) {
+                        return (offset, false);
+                    }
+                    break;
+                }
+                offset += comment.len();
+                self.state = ParseState::Comment;
+                continue;
+            }
+            match self.comment_end(rest, eof) {
+                Some((end, 0)) => {
+                    // Wait for the byte after a return
+                    self.comment_line.extend_from_slice(&rest[..end]);
+                    offset += end;
+                    self.bytes = base + offset as u64;
+                    return (offset, false);
+                },
+                Some((end, used)) => {
+                    self.comment_line.extend_from_slice(&rest[..end]);
+                    offset += used;
+                    self.lines += 1;
+                    self.bytes = base + offset as u64;
+                    self.end_comment();
+                    // Whatever follows starts a record, unless it is another comment
+                    self.in_record = offset < input.len();
+                },
+                None => {
+                    self.comment_line.extend_from_slice(rest);
+                    offset = input.len();
+                }
+            }
+        }
+        (offset, true)
+    }
+
+    // Length of the comment in `rest`, and how much to use including the line ending
+    fn comment_end(&mut self, rest: &[u8], eof: bool) -> Option<(usize, usize)> {
+        let terminator = match self.terminator {
+            Some(ref terminator) => terminator,
+            None if self.any_newline => {
+                let end = rest.iter().position(|&b| b == b'\n' || b == b'\r')?;
+                return match rest.get(end + 1) {
+                    _ if rest[end] == b'\n' => Some((end, end + 1)),
+                    Some(b'\n') => Some((end, end + 2)),
+                    None if !eof => Some((end, 0)),
+                    _ => Some((end, end + 1))
+                };
+            },
+            None => return rest.iter().position(|&b| b == b'\n').map(|end| (end, end + 1))
+        };
+        for split in 1..terminator.len() {
+            if self.comment_line.ends_with(&terminator[..split]) && rest.starts_with(&terminator[split..]) {
+                let len = self.comment_line.len() - split;
+                self.comment_line.truncate(len);
+                return Some((0, terminator.len() - split));
+            }
+        }
+        rest.windows(terminator.len()).position(|window| window == &terminator[..]).map(|end| (end, end + terminator.len()))
+    }
+
+    // Parses `input` until a record is completed or the input runs out
+    fn scan(&mut self, input: &[u8], record: &mut ByteRecord, eof: bool) -> (usize, ParseResult) {
+        let base = self.bytes;
+        if !input.is_empty() && self.state != ParseState::Comment {
+            self.in_record = true;
+        }
+        let mut offset = 0usize;
+        if self.comment.is_some() {
+            let (skipped, complete) = self.skip_comments(input, eof);
+            offset = skipped;
+            if !complete {
+                self.bytes = base + offset as u64;
+                return (offset, ParseResult::InputEmpty);
+            }
+        }
+        while offset < input.len() {
+            let rest = &input[offset..];
+
+            if self.trim_leading && self.state == ParseState::Neutral {
+                // Skip leading whitespace, so that a quote after it still starts a quoted field
+                let skipped = self.leading_whitespace(rest);
+                if skipped > 0 {
+                    offset += skipped;
+                    continue;
+                }
+            }
+
+            // Copy runs of ordinary bytes inside a field in one go
+            let in_quotes = match self.state {
+                ParseState::Neutral | ParseState::InField => Some(false),
+                ParseState::InQuotedField => Some(true),
+                ParseState::EncounteredQuoteInQuotedField | ParseState::Comment => None
+            };
+            if let Some(in_quotes) = in_quotes {
+                let run = self.scanner.run_len(rest,in_quotes);
+                if run > 0 {
+                    self.note_data(record,&rest[..run],base + offset as u64);
+                    // Single bytes are cheaper to push than to copy
+                    if run == 1 {
+                        record.push_byte(rest[0]);
+                    } else {
+                        record.extend_field(&rest[..run]);
+                    }
+                    offset += run;
+                    if self.state == ParseState::Neutral {
+                        self.state = ParseState::InField;
+                    }
+                    continue;
+                }
+            }
+
+            let (token, token_len) = match self.token(rest, eof) {
+                Some(token) => token,
+                None => break
+            };
+            self.bytes = base + offset as u64;
+
+            match token {
+                Token::Newline => {
+                    self.lines += 1;
+                },
+                // An escaped line break, but not the `\n` escape sequence, which stays on its line
+                Token::Escaped(b'\n') if rest[token_len - 1] == b'\n' => {
+                    self.lines += 1;
+                },
+                // A return is only allowed as part of a CRLF line ending
+                Token::Return { before_newline: false } if self.state != ParseState::InQuotedField => {
+                    let pos = self.position();
+                    self.violation(Error::StrayCarriageReturn { pos });
+                },
+                _ => {}
+            }
+
+            let mut record_end = false;
+            let mut field_end = false;
+            match self.state {
+                ParseState::Neutral => {
+                    match token {
+                        Token::Quote => { //Start of quoted field
+                            self.quote_position = self.position();
+                            self.quoted = true;
+                            self.state = ParseState::InQuotedField;
+                        },
+                        Token::Delimiter => { // empty field
+                            self.bytes += token_len as u64;
+                            self.end_field(record);
+                            field_end = true;
+                        },
+                        Token::Newline => { // Newline outside of quoted field. End of row.
+                            self.bytes += token_len as u64;
+                            self.end_field(record);
+                            record_end = true;
+                        },
+                        Token::Return { .. } => { // Return outside of quoted field. Eat it and keep going
+                        },
+                        Token::Escaped(b) => {
+                            self.escaped_field();
+                            self.note_data(record,&[b],self.bytes + token_len as u64 - 1);
+                            record.push_byte(b);
+                            self.state = ParseState::InField;
+                        },
+                        Token::Byte(b) => { // Anything else is unquoted data
+                            self.note_data(record,&[b],self.bytes);
+                            record.push_byte(b);
+                            self.state = ParseState::InField;
+                        }
+                    }
+                },
+                ParseState::InQuotedField => {
+                     match token {
+                        Token::Quote => {
+                            self.state = ParseState::EncounteredQuoteInQuotedField
+                        },
+                        Token::Escaped(b) => {
+                            self.escaped_field();
+                            self.note_data(record,&[b],self.bytes + token_len as u64 - 1);
+                            record.push_byte(b);
+                        },
+                        _ => { //Anything else is data
+                            self.note_data(record,&rest[..token_len],self.bytes);
+                            record.extend_field(&rest[..token_len]);
+                        }
+                    }
+                },
+                ParseState::InField => {
+                     match token {
+                        Token::Delimiter => {
+                            self.bytes += token_len as u64;
+                            self.end_field(record);
+                            field_end = true;
+                        },
+                        Token::Newline => {
+                            self.bytes += token_len as u64;
+                            self.end_field(record);
+                            record_end = true;
+                        },
+                        Token::Return { .. } => { // Return outside of quoted field. Eat it and keep going
+                        },
+                        Token::Quote => { // quote inside an unquoted field, keep it as data
+                            let pos = self.position();
+                            self.violation(Error::UnexpectedQuote { pos });
+                            self.note_data(record,&rest[..token_len],self.bytes);
+                            record.extend_field(&rest[..token_len]);
+                        },
+                        Token::Escaped(b) => {
+                            self.escaped_field();
+                            self.note_data(record,&[b],self.bytes + token_len as u64 - 1);
+                            record.push_byte(b);
+                        },
+                        Token::Byte(b) => {
+                            self.note_data(record,&[b],self.bytes);
+                            record.push_byte(b);
+                        }
+                    }
+                },
+                ParseState::EncounteredQuoteInQuotedField => {
+                     match token {
+                        Token::Quote => { // 2nd " in a row inside quoted field - escaped quote
+                            self.note_data(record,&rest[..token_len],self.bytes);
+                            record.extend_field(&rest[..token_len]);
+                            self.state = ParseState::InQuotedField;
+                        },
+                        Token::Delimiter => { // Field separator, end of quoted field
+                            self.bytes += token_len as u64;
+                            self.end_field(record);
+                            field_end = true;
+                        },
+                        Token::Newline => { // New line, end of quoted field
+                            self.bytes += token_len as u64;
+                            self.end_field(record);
+                            record_end = true;
+                        },
+                        Token::Return { .. } => { // Carriage Return after quoted field. discard.
+                        },
+                        Token::Byte(b) if self.trim_trailing && self.whitespace.contains(&b) => { // whitespace after quoted field, discard
+                        },
+                        Token::Escaped(b) | Token::Byte(b) => { // data after quoted field, treat it as data and add to existing data
+                            if let Token::Escaped(..) = token {
+                                self.escaped_field();
+                            }
+                            let pos = self.position();
+                            self.violation(Error::UnexpectedDataAfterQuote { pos });
+                            self.note_data(record,&[b],self.bytes + token_len as u64 - 1);
+                            record.push_byte(b);
+                            self.state = ParseState::InField;
+                        }
+                    }
+                },
+                ParseState::Comment => unreachable!("comment lines are skipped before tokenizing")
+            }
+            offset += token_len;
+            if record_end {
+                if self.newline_type.is_none() {
+                    self.newline_type = Some(newline_type(&input[..offset], token_len, self.terminator.is_none()));
+                }
+                self.bytes = base + offset as u64;
+                self.end_record();
+                return (offset, ParseResult::Record);
+            }
+            if field_end && self.stop_at_fields {
+                self.bytes = base + offset as u64;
+                return (offset, ParseResult::Field);
+            }
+        }
+        self.bytes = base + offset as u64;
+        (offset, ParseResult::InputEmpty)
+    }
+}
+
+// The byte that an escaped byte stands for, with the usual C escapes
+pub(crate) fn unescape(b: u8) -> u8 {
+    match b {
+        b'n' => b'\n',
+        b'r' => b'\r',
+        b't' => b'\t',
+        b'0' => 0,
+        _ => b
+    }
+}
+
+// Length of `literal` if `rest` starts with it, or None if it may continue in the next input
+#[inline]
+fn literal_len(rest: &[u8], literal: &[u8], eof: bool) -> Option<usize> {
+    if literal.first() != Some(&rest[0]) {
+        return Some(0);
+    }
+    if literal.len() == 1 || rest.starts_with(literal) {
+        return Some(literal.len());
+    }
+    if !eof && literal.starts_with(rest) {
+        return None;
+    }
+    Some(0)
+}
+
+// A return, which needs the next byte to tell whether it is part of a CRLF line ending
+#[inline]
+fn return_token(rest: &[u8], eof: bool) -> Option<(Token, usize)> {
+    if rest.len() == 1 && !eof {
+        return None;
+    }
+    Some((Token::Return { before_newline: rest.get(1) == Some(&b'\n') }, 1))
+}
+
+// The style of the line ending of `parsed`, which is `len` bytes long
+#[cold]
+fn newline_type(parsed: &[u8], len: usize, crlf: bool) -> NewlineType {
+    let mut ending = &parsed[parsed.len() - len..];
+    if crlf && ending == b"\n" && parsed.len() > 1 && parsed[parsed.len() - 2] == b'\r' {
+        ending = &parsed[parsed.len() - 2..];
+    }
+    match ending {
+        b"\n" => NewlineType::UnixStyle,
+        b"\r\n" => NewlineType::WindowsStyle,
+        _ => NewlineType::Custom(String::from_utf8_lossy(ending).into_owned())
+    }
+}
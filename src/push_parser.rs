@@ -0,0 +1,500 @@
+use std::mem::{replace,take};
+use std::str;
+use std::ops::Range;
+use crate::error::{Error,Position,Result};
+use crate::headers::{Headers,Row};
+use crate::parser::{unescape,ParseResult,Parser};
+use crate::reader::{ColumnCount,SimpleCsvReaderOptions,Strictness};
+use crate::record::{make_lossy,ByteRecord,StringRecord};
+use crate::visitor::{FieldInfo,RecordInfo,Visitor};
+use crate::writer::NewlineType;
+
+// Reserving space for the column buffer initially seems to significantly increase performance
+// Especially for column lengths <STRING_INITIAL_CAPACITY
+static STRING_INITIAL_CAPACITY: usize = 64usize;
+
+/// Parses input that is pushed to it in chunks of any size, for event loops and decoders that hand out bytes as they arrive.
+///
+/// Partial fields, quotes, line endings and UTF-8 sequences that are split between chunks are kept until the next chunk.
+/// `SimpleCsvReader` and the async reader are built on it and parse in exactly the same way.
+pub struct SimpleCsvPushParser {
+    machine: Parser,
+    record: StringRecord,
+    row_data: Vec<String>,
+    nullable_row: Vec<Option<String>>,
+    // `nulls` as the parser decodes them, and whether they hold an escape
+    nulls: Vec<(Vec<u8>,bool)>,
+    options: SimpleCsvReaderOptions,
+    headers: Option<Headers>,
+    headers_read: bool,
+    // The number of fields in the first record, for `column_count`
+    columns: Option<usize>,
+    // The number of empty fields `ColumnCount::Pad` added to the last record
+    padding: usize
+}
+
+impl SimpleCsvPushParser {
+    pub fn new(options: SimpleCsvReaderOptions) -> SimpleCsvPushParser {
+        SimpleCsvPushParser {
+            machine : Parser::new(&options),
+            record : StringRecord::new(),
+            row_data : Vec::new(),
+            nullable_row: Vec::new(),
+            nulls: decode_nulls(&options),
+            options,
+            headers: None,
+            headers_read: false,
+            columns: None,
+            padding: 0
+        }
+    }
+
+    /// Parses `input` up to the end of the next record.
+    /// Returns the number of bytes of `input` that were used, and the record if one was completed.
+    /// Pass the rest of `input` to `feed` again to get the records after it.
+    /// Without a record all of `input` has been used, and more input is needed.
+    /// When `has_headers` is set, the first record is kept as the header row instead of being returned.
+    pub fn feed(&mut self, input: &[u8]) -> (usize, Option<Result<&StringRecord>>) {
+        let mut used = 0;
+        loop {
+            let (consumed, done) = self.parse(&input[used..]);
+            used += consumed;
+            if !done {
+                return (used, None);
+            }
+            if self.wants_headers() {
+                let row = self.record_result();
+                if let Err(e) = self.set_headers(Some(row)) {
+                    return (used, Some(Err(e)));
+                }
+                continue;
+            }
+            return (used, Some(self.completed_record()));
+        }
+    }
+
+    /// Signals that there is no more input, and returns the last record if the input did not end with a line ending.
+    /// Call `finish` until it returns `None`.
+    pub fn finish(&mut self) -> Option<Result<&StringRecord>> {
+        while self.end_input() {
+            if self.wants_headers() {
+                let row = self.record_result();
+                if let Err(e) = self.set_headers(Some(row)) {
+                    return Some(Err(e));
+                }
+                continue;
+            }
+            return Some(self.completed_record());
+        }
+        None
+    }
+
+    fn completed_record(&mut self) -> Result<&StringRecord> {
+        self.record_result()?;
+        self.string_record()
+    }
+
+    /// Parses `input` into the current record.
+    /// Returns the number of bytes used and whether a record was completed.
+    #[inline]
+    pub(crate) fn parse(&mut self, input: &[u8]) -> (usize, bool) {
+        let (consumed, result) = self.machine.parse(input,self.record.bytes_mut());
+        let done = result == ParseResult::Record;
+        if done && self.options.column_count != ColumnCount::Flexible {
+            self.check_columns();
+        }
+        (consumed, done)
+    }
+
+    /// Signals the end of the input. Returns true if that completed a record
+    pub(crate) fn end_input(&mut self) -> bool {
+        let done = self.machine.finish(self.record.bytes_mut()) == ParseResult::Record;
+        if done && self.options.column_count != ColumnCount::Flexible {
+            self.check_columns();
+        }
+        done
+    }
+
+    /// Like `parse`, but passes each field to `visitor` as soon as it is complete
+    pub(crate) fn parse_visiting<V: Visitor>(&mut self, input: &[u8], visitor: &mut V) -> (usize, bool) {
+        self.machine.set_stop_at_fields(true);
+        let mut used = 0;
+        let done = loop {
+            let (consumed, result) = self.machine.parse(&input[used..],self.record.bytes_mut());
+            used += consumed;
+            match result {
+                ParseResult::InputEmpty => break false,
+                ParseResult::Field => self.visit_fields(visitor),
+                ParseResult::Record => {
+                    self.end_visited_record(visitor);
+                    break true;
+                }
+            }
+        };
+        self.machine.set_stop_at_fields(false);
+        (used, done)
+    }
+
+    /// Like `end_input`, for `parse_visiting`
+    pub(crate) fn end_input_visiting<V: Visitor>(&mut self, visitor: &mut V) -> bool {
+        self.machine.set_stop_at_fields(true);
+        let done = loop {
+            match self.machine.finish(self.record.bytes_mut()) {
+                ParseResult::InputEmpty => break false,
+                ParseResult::Field => self.visit_fields(visitor),
+                ParseResult::Record => {
+                    self.end_visited_record(visitor);
+                    break true;
+                }
+            }
+        };
+        self.machine.set_stop_at_fields(false);
+        done
+    }
+
+    // Passes the fields in the record to the visitor and takes them out of it
+    fn visit_fields<V: Visitor>(&mut self, visitor: &mut V) {
+        let positions = self.machine.field_positions();
+        let quoted = self.machine.field_quoted();
+        let first = positions.len() - self.record.len();
+        let columns = match self.options.column_count {
+            ColumnCount::Flexible => None,
+            _ => self.columns
+        };
+        for (offset, field) in self.record.as_byte_record().iter().enumerate() {
+            let index = first + offset;
+            if self.machine.failed() || matches!(columns, Some(columns) if index >= columns) {
+                break;
+            }
+            visitor.on_field(field, FieldInfo { index, quoted: quoted[index], position: positions[index] });
+        }
+        self.record.bytes_mut().clear();
+    }
+
+    // Passes the rest of the record that was just completed
+    fn end_visited_record<V: Visitor>(&mut self, visitor: &mut V) {
+        self.visit_fields(visitor);
+        if self.options.column_count != ColumnCount::Flexible {
+            self.check_columns();
+            self.visit_fields(visitor);
+        }
+        if !self.machine.failed() {
+            visitor.on_record_end(RecordInfo { position: self.position(), fields: self.machine.field_positions().len() });
+        }
+    }
+
+    // Holds the record that was just completed to the number of fields in the first one
+    #[inline(never)]
+    fn check_columns(&mut self) {
+        let found = self.machine.field_positions().len();
+        let expected = *self.columns.get_or_insert(found);
+        self.padding = 0;
+        if found == expected {
+            return;
+        }
+        match self.options.column_count {
+            ColumnCount::Pad if found < expected => {
+                self.machine.resize_record(self.record.bytes_mut(),expected);
+                self.padding = expected - found;
+            },
+            ColumnCount::Truncate if found > expected => self.machine.resize_record(self.record.bytes_mut(),expected),
+            _ => {
+                let pos = self.machine.record_position();
+                self.machine.fail(Error::FieldCount { pos, expected, found });
+            }
+        }
+    }
+
+    /// The outcome of parsing the record that was just completed
+    pub(crate) fn record_result(&mut self) -> Result<()> {
+        match self.machine.take_error() {
+            Some(e) => Err(e),
+            None => Ok(())
+        }
+    }
+
+    /// Position of the next byte to be parsed
+    pub(crate) fn next_position(&self) -> Position {
+        self.machine.position()
+    }
+
+    // Makes positions count from `pos`, for input that starts part way through a larger file
+    pub(crate) fn start_at(&mut self, pos: Position) {
+        self.machine.start_at(pos);
+    }
+
+    // Makes sure the last parsed record is valid UTF-8
+    fn decode_record(&mut self) -> Result<()> {
+        let invalid = self.record.as_byte_record().invalid_fields();
+        if !invalid.is_empty() {
+            if self.options.strictness != Strictness::Lenient {
+                for index in invalid {
+                    let pos = self.utf8_position(index);
+                    self.machine.violation(Error::Utf8 { pos });
+                }
+            }
+            make_lossy(self.record.bytes_mut());
+        }
+        self.record_result()
+    }
+
+    // Position of the first invalid byte of field `index`
+    #[cold]
+    fn utf8_position(&self, index: usize) -> Position {
+        let field = &self.record.as_byte_record()[index];
+        let offset = str::from_utf8(field).err().map_or(0,|e| e.valid_up_to());
+        self.machine.data_position(index,offset).unwrap_or(self.machine.field_positions()[index])
+    }
+
+    // Copies the last parsed record into row_data, reusing its Strings
+    fn decode_row(&mut self) -> Result<()> {
+        self.decode_record()?;
+        self.row_data.truncate(self.record.len());
+        for (index, field) in self.record.iter().enumerate() {
+            if index == self.row_data.len() {
+                self.row_data.push(String::with_capacity(STRING_INITIAL_CAPACITY));
+            }
+            let value = &mut self.row_data[index];
+            value.clear();
+            value.push_str(field);
+        }
+        Ok(())
+    }
+
+    /// Whether the next record has to be read as the header row
+    pub(crate) fn wants_headers(&self) -> bool {
+        self.options.has_headers && !self.headers_read
+    }
+
+    /// Takes the result of reading the header row. `None` means the input was empty
+    pub(crate) fn set_headers(&mut self, row: Option<Result<()>>) -> Result<()> {
+        self.headers_read = true;
+        match row {
+            Some(Ok(..)) => {
+                self.decode_row()?;
+                let names = take(&mut self.row_data);
+                self.headers = Some(Headers::new(names));
+                Ok(())
+            },
+            Some(Err(e)) => Err(e),
+            None => Ok(())
+        }
+    }
+
+    /// The header row, once it has been parsed. Always `None` unless `has_headers` is set
+    pub fn headers(&self) -> Option<&Headers> {
+        self.headers.as_ref()
+    }
+
+    pub(crate) fn row(&mut self) -> Result<&[String]> {
+        self.decode_row()?;
+        Ok(&self.row_data)
+    }
+
+    /// Like `row`, but hands over the row instead of borrowing it
+    pub(crate) fn take_row(&mut self) -> Result<Vec<String>> {
+        // Instead of cloning the row, swap in a new Vec with the same capacity
+        self.decode_row()?;
+        let cap = self.row_data.capacity();
+        Ok(replace(&mut self.row_data, Vec::with_capacity(cap)))
+    }
+
+    pub(crate) fn nullable_row(&mut self) -> Result<&[Option<String>]> {
+        self.decode_record()?;
+        let quoted = self.machine.field_quoted();
+        let escaped = self.machine.escaped_fields();
+        let padded = self.record.len() - self.padding;
+        self.nullable_row.truncate(self.record.len());
+        for (index, field) in self.record.iter().enumerate() {
+            if index == self.nullable_row.len() {
+                self.nullable_row.push(None);
+            }
+            let has_escape = escaped.binary_search(&index).is_ok();
+            let is_null = index >= padded || (!quoted[index] && self.nulls.iter().any(|&(ref null, escape)| escape == has_escape && null[..] == *field.as_bytes()));
+            match self.nullable_row[index] {
+                _ if is_null => self.nullable_row[index] = None,
+                Some(ref mut value) => {
+                    value.clear();
+                    value.push_str(field);
+                },
+                None => self.nullable_row[index] = Some(field.to_string())
+            }
+        }
+        Ok(&self.nullable_row)
+    }
+
+    pub(crate) fn named_row(&mut self) -> Result<Row<'_>> {
+        self.decode_row()?;
+        Ok(Row::new(&self.row_data,self.headers.as_ref()))
+    }
+
+    pub(crate) fn byte_record(&self) -> &ByteRecord {
+        self.record.as_byte_record()
+    }
+
+    pub(crate) fn string_record(&mut self) -> Result<&StringRecord> {
+        self.decode_record()?;
+        Ok(&self.record)
+    }
+
+    /// Position of the start of the most recently completed record
+    pub fn position(&self) -> Position {
+        self.machine.record_position()
+    }
+
+    /// Byte offsets of the start and end of the most recently completed record, including its line ending
+    pub fn byte_range(&self) -> Range<u64> {
+        self.machine.record_position().byte..self.machine.position().byte
+    }
+
+    /// Start positions of the fields of the most recently completed record
+    #[cfg(feature = "mmap")]
+    pub(crate) fn field_positions(&self) -> &[Position] {
+        self.machine.field_positions()
+    }
+
+    /// Whether each field of the most recently completed record was enclosed in quotes
+    pub fn quoted_fields(&self) -> &[bool] {
+        self.machine.field_quoted()
+    }
+
+    /// Violations found in the most recently completed record when `strictness` is `Strictness::Warn`
+    pub fn warnings(&self) -> &[Error] {
+        self.machine.warnings()
+    }
+
+    /// Comment lines skipped right before the most recently completed record, without the comment character and line ending
+    pub fn comments(&self) -> &[String] {
+        self.machine.comments()
+    }
+
+    /// The line ending of the first record that ended with one, in the form `SimpleCsvWriterOptions::newline_type` takes
+    pub fn newline_type(&self) -> Option<&NewlineType> {
+        self.machine.newline_type()
+    }
+}
+
+// Decodes the escapes in `nulls`, so that `\N` matches the field the parser makes of it
+fn decode_nulls(options: &SimpleCsvReaderOptions) -> Vec<(Vec<u8>,bool)> {
+    let mut escape_bytes = [0u8; 4];
+    let escape = options.escape
+        .filter(|&escape| Some(escape) != options.text_enclosure)
+        .map(|escape| escape.encode_utf8(&mut escape_bytes).as_bytes());
+    options.nulls.iter().map(|null| {
+        let null = null.as_bytes();
+        let escape = match escape {
+            Some(escape) => escape,
+            None => return (null.to_vec(), false)
+        };
+        let (mut decoded, mut has_escape, mut i) = (Vec::new(), false, 0);
+        while i < null.len() {
+            if null[i..].starts_with(escape) && i + escape.len() < null.len() {
+                decoded.push(unescape(null[i + escape.len()]));
+                has_escape = true;
+                i += escape.len() + 1;
+            } else {
+                decoded.push(null[i]);
+                i += 1;
+            }
+        }
+        (decoded, has_escape)
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::reader::{SimpleCsvReader,Trim};
+
+    // Feeds `input` in chunks of `size` bytes and collects every record or error
+    fn push_all(input: &[u8], size: usize, options: SimpleCsvReaderOptions) -> Vec<Result<Vec<String>>> {
+        let mut parser = SimpleCsvPushParser::new(options);
+        let mut records = Vec::new();
+        for mut chunk in input.chunks(size) {
+            while !chunk.is_empty() {
+                let (used, record) = parser.feed(chunk);
+                if let Some(record) = record {
+                    records.push(record.map(|record| record.iter().map(|field| field.to_string()).collect()));
+                }
+                chunk = &chunk[used..];
+            }
+        }
+        while let Some(record) = parser.finish() {
+            records.push(record.map(|record| record.iter().map(|field| field.to_string()).collect()));
+        }
+        records
+    }
+
+    #[test]
+    fn push_parser_chunk_sizes() {
+        let input = "h1\u{00A9}h2\r\n\"\u{00E9}\u{00A9}\r\n\"\"\"\u{00A9}x\u{00E9}\r\n\u{00A9}\r\n\"end".as_bytes();
+        let options = SimpleCsvReaderOptions { delimiter: '\u{00A9}', has_headers: true, ..Default::default() };
+        let expected: Vec<Vec<String>> = SimpleCsvReader::with_options(input,options.clone()).map(|row| row.unwrap()).collect();
+        assert_eq!(expected.len(), 3);
+        for size in 1..input.len() + 1 {
+            let records: Vec<Vec<String>> = push_all(input,size,options.clone()).into_iter().map(|record| record.unwrap()).collect();
+            assert_eq!(records, expected);
+        }
+    }
+
+    #[test]
+    fn push_parser_split_token() {
+        let options = SimpleCsvReaderOptions { delimiter_str: Some("||".to_string()), ..Default::default() };
+        let mut parser = SimpleCsvPushParser::new(options);
+        assert_eq!(parser.feed(b"a|").0, 2);
+        // The record after the split delimiter comes out of the same call
+        let (used, record) = parser.feed(b"|b\nzzzz");
+        assert_eq!(used, 3);
+        assert_eq!(record.unwrap().unwrap().iter().collect::<Vec<_>>(), vec!["a","b"]);
+        // Passing on the rest of the chunk, as usual
+        let (used, record) = parser.feed(b"zzzz");
+        assert_eq!(used, 4);
+        assert!(record.is_none());
+        assert_eq!(parser.finish().unwrap().unwrap().iter().collect::<Vec<_>>(), vec!["zzzz"]);
+    }
+
+    #[test]
+    fn push_parser_utf8_position_across_chunks() {
+        // A trimmed space, a doubled quote and an escape come before the first invalid byte,
+        // and each of them, the delimiter and the invalid byte itself may be split between chunks
+        let input = b"1|| \"a\"\"b\\,\xff\" ||2\nx||\xff\n\"\n\"||\"  \xff\"\n";
+        let options = SimpleCsvReaderOptions {
+            delimiter_str: Some("||".to_string()),
+            escape: Some('\\'),
+            trim: Trim::Both,
+            strictness: Strictness::Strict,
+            ..Default::default()
+        };
+        let expected = vec![
+            Position { byte: 11, line: 1, record: 0 },
+            Position { byte: 21, line: 2, record: 1 },
+            Position { byte: 31, line: 4, record: 2 }];
+        for size in 1..input.len() + 1 {
+            let positions: Vec<Position> = push_all(input,size,options.clone()).into_iter().map(|record| match record {
+                Err(Error::Utf8 { pos }) => pos,
+                _ => panic!("expected Utf8")
+            }).collect();
+            assert_eq!(positions, expected, "chunks of {} bytes", size);
+        }
+    }
+
+    #[test]
+    fn push_parser_errors_and_headers() {
+        let mut parser = SimpleCsvPushParser::new(SimpleCsvReaderOptions { has_headers: true, strictness: Strictness::Strict, ..Default::default() });
+        let (used, record) = parser.feed(b"a,b\n1,\"2\"x\n3,4");
+        assert_eq!(used, 11);
+        match record {
+            Some(Err(Error::UnexpectedDataAfterQuote { pos })) => assert_eq!(pos.byte, 9),
+            _ => panic!("expected an error")
+        }
+        assert_eq!(parser.headers().unwrap().index_of("b"), Ok(1));
+
+        let (used, record) = parser.feed(b"3,4");
+        assert_eq!(used, 3);
+        assert!(record.is_none());
+        assert_eq!(parser.finish().unwrap().unwrap().iter().collect::<Vec<_>>(), vec!["3","4"]);
+        assert_eq!(parser.byte_range(), 11..14);
+        assert!(parser.finish().is_none());
+    }
+}
@@ -1,43 +1,227 @@
-use std::vec::Vec;
-use std::mem::replace;
-use std::io::{BufRead,Result};
+use std::io::{self,BufRead};
+use std::ops::Range;
+use std::result;
 use std::default::Default;
+use crate::error::{Error,Position,Result};
+use crate::headers::{Headers,Row};
+use crate::options::{check_literals,OptionsError};
+use crate::push_parser::SimpleCsvPushParser;
+use crate::record::{ByteRecord,StringRecord};
+use crate::visitor::Visitor;
+use crate::writer::NewlineType;
 
-// Reserving space for the column Strings initially seems to significantly increase performance
-// Especially for column lengths <STRING_INITIAL_CAPACITY
-static STRING_INITIAL_CAPACITY: usize = 64usize;
+pub struct SimpleCsvReader<B: BufRead> {
+    parser: SimpleCsvPushParser,
+    input_reader: B
+}
 
-enum ParseState {
-    Neutral,
-    InField,
-    InQuotedField,
-    EncounteredQuoteInQuotedField,
-    EndOfRow
+/// How the reader handles input that does not conform to RFC 4180
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum Strictness {
+    /// Parse non-conformant input as described in the README, without reporting it
+    Lenient,
+    /// Parse like `Lenient`, but record each violation. See `SimpleCsvReader::warnings`
+    Warn,
+    /// Return an error instead of any row that violates RFC 4180.
+    /// Bare `\n` line endings are still accepted.
+    Strict
 }
 
+/// Which spaces and tabs the reader removes from fields.
+/// Whitespace that is also the delimiter is never trimmed.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum Trim {
+    /// Keep all whitespace. Whitespace before a quote makes the quote part of an unquoted field
+    None,
+    /// Remove whitespace at the start of fields. A quote after it still starts a quoted field
+    Leading,
+    /// Remove whitespace at the end of fields and after closing quotes
+    Trailing,
+    /// Remove whitespace around fields and at both ends of the text inside quotes
+    Both,
+    /// Remove whitespace around fields, but keep the text inside quotes as it is
+    Unquoted
+}
 
-pub struct SimpleCsvReader<B: BufRead> {
-    state: ParseState,
-    row_data: Vec<String>,
-    line_bytes: Vec<u8>,
-    column_buffer: String,
-    input_reader: B,
-    options: SimpleCsvReaderOptions
+/// What ends a record
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum RecordTerminator {
+    /// `\n`, optionally preceded by `\r`
+    Newline,
+    /// Only `\n`. A `\r` is data
+    Lf,
+    /// Only `\r\n`. A lone `\r` or `\n` is data
+    CrLf,
+    /// Only `\r`, as in files from classic Mac OS. A `\n` is data
+    Cr,
+    /// Any of `\n`, `\r\n` and `\r`, even mixed in one file
+    Any,
+    /// Any string, such as `"\x1e"` or `"|\n"`. A lone `\n` or `\r` is then ordinary data
+    Custom(String)
+}
+
+/// What happens to rows with a different number of fields than the first row, or the header row if there is one
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum ColumnCount {
+    /// Rows may have any number of fields
+    Flexible,
+    /// Return an `Error::FieldCount` instead of the row
+    Exact,
+    /// Fill up short rows with empty fields, which `next_nullable_row` returns as `None`. Long rows are an error as with `Exact`
+    Pad,
+    /// Drop the extra fields of long rows. Short rows are an error as with `Exact`
+    Truncate
 }
 
-#[derive(Copy,Clone)]
+#[derive(Clone)]
 pub struct SimpleCsvReaderOptions {
     pub delimiter: char,
-    pub text_enclosure: char
+    /// A delimiter of several characters, such as `||`. Takes the place of `delimiter` when set
+    pub delimiter_str: Option<String>,
+    pub terminator: RecordTerminator,
+    /// The quote around fields. With `None` every character is taken literally
+    pub text_enclosure: Option<char>,
+    /// Treat the first row as column names instead of data
+    pub has_headers: bool,
+    pub strictness: Strictness,
+    /// Skip lines that start with this character outside a quoted field. See `SimpleCsvReader::comments`.
+    /// A line with whitespace before it is a record, even when `trim` removes the whitespace
+    pub comment: Option<char>,
+    pub trim: Trim,
+    /// Take the character after this one as data, so that `\"` and `\,` need no doubling or quoting.
+    /// `n`, `r`, `t` and `0` after it stand for a newline, a return, a tab and a NUL byte
+    pub escape: Option<char>,
+    pub column_count: ColumnCount,
+    /// Unquoted fields that `next_nullable_row` returns as `None`, such as `\N`, `NULL` or `NA`.
+    /// `""` stands for empty unquoted fields. Quoted fields are never null
+    pub nulls: Vec<String>
 }
 
 impl Default for SimpleCsvReaderOptions {
     fn default() -> SimpleCsvReaderOptions {
         SimpleCsvReaderOptions {
             delimiter: ',',
-            text_enclosure: '"'
+            delimiter_str: None,
+            terminator: RecordTerminator::Newline,
+            text_enclosure: Some('"'),
+            has_headers: false,
+            strictness: Strictness::Lenient,
+            comment: None,
+            trim: Trim::None,
+            escape: None,
+            column_count: ColumnCount::Flexible,
+            nulls: Vec::new()
+        }
+    }
+}
+
+impl SimpleCsvReaderOptions {
+    // The delimiter as it appears in the input
+    pub(crate) fn delimiter_bytes(&self) -> Vec<u8> {
+        match self.delimiter_str {
+            Some(ref delimiter) if !delimiter.is_empty() => delimiter.as_bytes().to_vec(),
+            _ => {
+                let mut delimiter_bytes = [0u8; 4];
+                self.delimiter.encode_utf8(&mut delimiter_bytes).as_bytes().to_vec()
+            }
         }
     }
+
+    /// Starts from the default options and checks the result in `build`
+    pub fn builder() -> SimpleCsvReaderOptionsBuilder {
+        SimpleCsvReaderOptionsBuilder { options: Default::default() }
+    }
+
+    /// Checks that the delimiter, quote, escape, comment character and terminator all differ
+    /// and that only the terminator holds line ending characters.
+    /// An escape that is the quote is allowed, and means doubled quotes as no escape would
+    pub fn validate(&self) -> result::Result<(), OptionsError> {
+        let mut literals = Vec::new();
+        match self.delimiter_str {
+            Some(ref delimiter) => literals.push(("delimiter_str",delimiter.clone())),
+            None => literals.push(("delimiter",self.delimiter.to_string()))
+        }
+        let escape = self.escape.filter(|&c| Some(c) != self.text_enclosure);
+        let characters = [("text_enclosure",self.text_enclosure), ("escape",escape), ("comment",self.comment)];
+        for &(option, c) in characters.iter() {
+            if let Some(c) = c {
+                literals.push((option,c.to_string()));
+            }
+        }
+        let terminator = match self.terminator {
+            RecordTerminator::Custom(ref terminator) => Some(("terminator",terminator.clone())),
+            _ => None
+        };
+        check_literals(&literals,terminator)
+    }
+}
+
+/// Sets `SimpleCsvReaderOptions` one by one and refuses combinations the parser cannot make sense of
+pub struct SimpleCsvReaderOptionsBuilder {
+    options: SimpleCsvReaderOptions
+}
+
+impl SimpleCsvReaderOptionsBuilder {
+    pub fn delimiter(mut self, delimiter: char) -> SimpleCsvReaderOptionsBuilder {
+        self.options.delimiter = delimiter;
+        self
+    }
+
+    pub fn delimiter_str(mut self, delimiter: &str) -> SimpleCsvReaderOptionsBuilder {
+        self.options.delimiter_str = Some(delimiter.to_string());
+        self
+    }
+
+    pub fn terminator(mut self, terminator: RecordTerminator) -> SimpleCsvReaderOptionsBuilder {
+        self.options.terminator = terminator;
+        self
+    }
+
+    pub fn text_enclosure(mut self, text_enclosure: Option<char>) -> SimpleCsvReaderOptionsBuilder {
+        self.options.text_enclosure = text_enclosure;
+        self
+    }
+
+    pub fn has_headers(mut self, has_headers: bool) -> SimpleCsvReaderOptionsBuilder {
+        self.options.has_headers = has_headers;
+        self
+    }
+
+    pub fn strictness(mut self, strictness: Strictness) -> SimpleCsvReaderOptionsBuilder {
+        self.options.strictness = strictness;
+        self
+    }
+
+    pub fn comment(mut self, comment: Option<char>) -> SimpleCsvReaderOptionsBuilder {
+        self.options.comment = comment;
+        self
+    }
+
+    pub fn trim(mut self, trim: Trim) -> SimpleCsvReaderOptionsBuilder {
+        self.options.trim = trim;
+        self
+    }
+
+    pub fn escape(mut self, escape: Option<char>) -> SimpleCsvReaderOptionsBuilder {
+        self.options.escape = escape;
+        self
+    }
+
+    pub fn column_count(mut self, column_count: ColumnCount) -> SimpleCsvReaderOptionsBuilder {
+        self.options.column_count = column_count;
+        self
+    }
+
+    pub fn nulls(mut self, nulls: &[&str]) -> SimpleCsvReaderOptionsBuilder {
+        self.options.nulls = nulls.iter().map(|null| null.to_string()).collect();
+        self
+    }
+
+    /// Returns the options, or the first problem `SimpleCsvReaderOptions::validate` finds with them
+    pub fn build(self) -> result::Result<SimpleCsvReaderOptions, OptionsError> {
+        self.options.validate()?;
+        Ok(self.options)
+    }
 }
 
 
@@ -48,159 +232,195 @@ impl<B: BufRead> SimpleCsvReader<B> {
     }
     
     pub fn with_options(buffer: B, options: SimpleCsvReaderOptions)  -> SimpleCsvReader<B> {
-        
         SimpleCsvReader {
-            state : ParseState::Neutral,
-            row_data : Vec::new(),
-            line_bytes : Vec::new(),
-            column_buffer : String::with_capacity(STRING_INITIAL_CAPACITY),
-            input_reader : buffer,
-            options: options
+            parser: SimpleCsvPushParser::new(options),
+            input_reader : buffer
         }
     }
-    
-    #[inline]
-    fn new_column(&mut self) {
-        let column_data = replace(&mut self.column_buffer,String::with_capacity(STRING_INITIAL_CAPACITY));
-        self.row_data.push(column_data);
-        self.state = ParseState::Neutral;
+
+    /// Returns the header row, reading it first if no row has been read yet.
+    /// Returns `None` if the reader was not configured with `has_headers` or the input is empty.
+    pub fn headers(&mut self) -> Option<Result<&Headers>> {
+        if let Err(e) = self.read_headers() {
+            return Some(Err(e));
+        }
+        self.parser.headers().map(Ok)
     }
-    
-    fn process_line(&mut self) {
-        let line = String::from_utf8_lossy(&*self.line_bytes).into_owned();
-            
-        let delimiter = self.options.delimiter;
-        let text_enclosure = self.options.text_enclosure;
-        for c in line.chars() {
-            match self.state {
-                ParseState::Neutral => {
-                    match c {
-                        _ if c==text_enclosure => { //Start of quoted field
-                            self.state = ParseState::InQuotedField;
-                        },
-                        _ if c==delimiter => { // empty field
-                            self.row_data.push(String::new());
-                        },
-                        '\n' => { // Newline outside of quoted field. End of row.
-                            self.new_column();
-                            self.state = ParseState::EndOfRow;
-                        },
-                        '\r' => { // Return outside of quoted field. Eat it and keep going
-                        },
-                        _ => { // Anything else is unquoted data
-                            self.column_buffer.push(c);
-                            self.state = ParseState::InField;
-                        }
-                    }
-                },
-                ParseState::InQuotedField => {
-                     match c {
-                        _ if c==text_enclosure => {
-                            self.state = ParseState::EncounteredQuoteInQuotedField
-                        },
-                        _ => { //Anything else is data
-                            self.column_buffer.push(c);
-                        } 
-                    }
-                },
-                ParseState::InField => {
-                     match c {
-                        _ if c==delimiter => {
-                            self.new_column();
-                        },
-                        '\n' => {
-                            self.new_column();
-                            self.state = ParseState::EndOfRow;
-                        },
-                        '\r' => { // Return outside of quoted field. Eat it and keep going
-                        },
-                        _ => {
-                            self.column_buffer.push(c);
-                        }
-                    }
-                },
-                ParseState::EncounteredQuoteInQuotedField => {
-                     match c {
-                        _ if c==text_enclosure => { // 2nd " in a row inside quoted field - escaped quote
-                            self.column_buffer.push(c);
-                            self.state = ParseState::InQuotedField;
-                        },
-                        _ if c==delimiter => { // Field separator, end of quoted field
-                            self.new_column();
-                        },
-                        '\n' => { // New line, end of quoted field
-                            self.new_column();
-                            self.state = ParseState::EndOfRow;
-                        },
-                        '\r' => { // Carriage Return after quoted field. discard.
-                        },
-                        _ => { // data after quoted field, treat it as data and add to existing data
-                            self.column_buffer.push(c);
-                            self.state = ParseState::InField;
-                        }
-                    }
-                },
-                ParseState::EndOfRow => {
-                    assert!(false,"Should never reach match for EndOfRow");
-                },
-                
-            }
+
+    fn read_headers(&mut self) -> Result<()> {
+        if !self.parser.wants_headers() {
+            return Ok(());
         }
-        
+        let row = self.read_row();
+        self.parser.set_headers(row)
     }
-    
-    pub fn next_row<'b>(&'b mut self) -> Option<Result<&'b [String]>> {
-    
-        // Reset state
-        self.row_data.truncate(0);
-        self.state = ParseState::Neutral;
-        let mut line_count = 0usize;
-        
-        // continually read lines. The match statement below will break once the end of row is reached
+
+    pub fn next_row(&mut self) -> Option<Result<&[String]>> {
+        if let Err(e) = self.read_headers() {
+            return Some(Err(e));
+        }
+        match self.read_row()? {
+            Ok(..) => Some(self.parser.row()),
+            Err(e) => Some(Err(e))
+        }
+    }
+
+    /// Like `next_row`, but unquoted fields listed in `nulls` are `None`
+    pub fn next_nullable_row(&mut self) -> Option<Result<&[Option<String>]>> {
+        if let Err(e) = self.read_headers() {
+            return Some(Err(e));
+        }
+        match self.read_row()? {
+            Ok(..) => Some(self.parser.nullable_row()),
+            Err(e) => Some(Err(e))
+        }
+    }
+
+    /// Like `next_row`, but the returned row can also be indexed by column name when `has_headers` is set
+    pub fn next_named_row(&mut self) -> Option<Result<Row<'_>>> {
+        if let Err(e) = self.read_headers() {
+            return Some(Err(e));
+        }
+        match self.read_row()? {
+            Ok(..) => Some(self.parser.named_row()),
+            Err(e) => Some(Err(e))
+        }
+    }
+
+    /// Like `next_row`, but returns the fields as raw bytes without decoding them as UTF-8
+    pub fn next_byte_record(&mut self) -> Option<Result<&ByteRecord>> {
+        if let Err(e) = self.read_headers() {
+            return Some(Err(e));
+        }
+        match self.read_row()? {
+            Ok(..) => Some(Ok(self.parser.byte_record())),
+            Err(e) => Some(Err(e))
+        }
+    }
+
+    /// Like `next_row`, but borrows every field from a single buffer that is reused from row to row.
+    /// Once the buffer has grown to fit the longest row, reading a row does not allocate.
+    pub fn next_record(&mut self) -> Option<Result<&StringRecord>> {
+        if let Err(e) = self.read_headers() {
+            return Some(Err(e));
+        }
+        match self.read_row()? {
+            Ok(..) => Some(self.parser.string_record()),
+            Err(e) => Some(Err(e))
+        }
+    }
+
+    /// Calls `visitor` with every field and the end of every remaining row, without decoding the fields as UTF-8.
+    /// Each field is passed as soon as it is parsed, so only the field being parsed is held in memory, not the whole row.
+    /// Stops at the first I/O error, or at the first row that violates RFC 4180 when `strictness` is `Strict`.
+    /// The fields of a failed row before the failure have been passed already, but the end of the row is not.
+    /// Calling `visit` again carries on with the next row.
+    pub fn visit<V: Visitor>(&mut self, visitor: &mut V) -> Result<()> {
+        self.read_headers()?;
+        while let Some(row) = self.read_with(|parser, input| match input {
+            Some(input) => parser.parse_visiting(input,visitor),
+            None => (0, parser.end_input_visiting(visitor))
+        }) {
+            row?;
+        }
+        Ok(())
+    }
+
+    // Like `next_record`, along with the position of the record
+    pub(crate) fn next_positioned_record(&mut self) -> Option<(Position,Result<&StringRecord>)> {
+        if let Err(e) = self.read_headers() {
+            return Some((self.parser.next_position(),Err(e)));
+        }
+        let read = self.read_row()?;
+        let pos = self.parser.position();
+        match read {
+            Ok(..) => Some((pos,self.parser.string_record())),
+            Err(e) => Some((pos,Err(e)))
+        }
+    }
+
+    #[cfg(feature = "mmap")]
+    pub(crate) fn input(&self) -> &B {
+        &self.input_reader
+    }
+
+    #[cfg(feature = "mmap")]
+    pub(crate) fn push_parser(&self) -> &SimpleCsvPushParser {
+        &self.parser
+    }
+
+    // Makes positions count from `pos`, for input that starts part way through a larger file
+    pub(crate) fn start_at(&mut self, pos: Position) {
+        self.parser.start_at(pos);
+    }
+
+    /// Position of the start of the most recently read row
+    pub fn position(&self) -> Position {
+        self.parser.position()
+    }
+
+    /// Byte offsets of the start and end of the most recently read row, including its line ending.
+    /// Useful for building an index of where each row is stored in the input.
+    pub fn byte_range(&self) -> Range<u64> {
+        self.parser.byte_range()
+    }
+
+    /// Whether each field of the most recently read row was enclosed in quotes
+    pub fn quoted_fields(&self) -> &[bool] {
+        self.parser.quoted_fields()
+    }
+
+    /// Violations found in the most recently read row when `strictness` is `Strictness::Warn`
+    pub fn warnings(&self) -> &[Error] {
+        self.parser.warnings()
+    }
+
+    /// Comment lines skipped right before the most recently read row, without the comment character and line ending.
+    /// Once the input is exhausted, the comment lines after the last row.
+    /// Comments before the header row can be read after calling `headers`.
+    pub fn comments(&self) -> &[String] {
+        self.parser.comments()
+    }
+
+    /// The line ending of the first row that ended with one, in the form `SimpleCsvWriterOptions::newline_type` takes.
+    /// Useful with `RecordTerminator::Any` to write files back with the line endings they were read with.
+    pub fn newline_type(&self) -> Option<&NewlineType> {
+        self.parser.newline_type()
+    }
+
+    fn read_row(&mut self) -> Option<Result<()>> {
+        self.read_with(|parser, input| match input {
+            Some(input) => parser.parse(input),
+            None => (0, parser.end_input())
+        })
+    }
+
+    // Feeds the parser straight from the reader's buffer until it completes a record.
+    // `parse` is given each buffer, or None at the end of the input, and returns how much it used and whether a record was completed
+    #[inline]
+    fn read_with<P>(&mut self, mut parse: P) -> Option<Result<()>> where P: FnMut(&mut SimpleCsvPushParser,Option<&[u8]>) -> (usize,bool) {
         loop {
-            // reset our line buffer
-            self.line_bytes.truncate(0);
-            // read (up to) new line character
-            let line_result = self.input_reader.read_until('\n' as u8, &mut self.line_bytes);
-            
-            match line_result {
-                // Read succeeded, no error & bytes read > 0
-                Ok(bytes_read) if bytes_read > 0 => {
-                    line_count += 1;
-                    self.process_line();
-                    
-                    // Exit the loop if we have reached the end of the row
-                    if let ParseState::EndOfRow = self.state {
+            let (consumed, done) = {
+                let input = match self.input_reader.fill_buf() {
+                    Ok(input) => input,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Some(Err(Error::Io { error: e, pos: self.parser.next_position() }))
+                };
+                if input.is_empty() {
+                    // EOF. Returns the last record if it did not end with a newline
+                    if parse(&mut self.parser,None).1 {
                         break;
                     }
-                },
-                // No error, but no data read (EOF)
-                Ok(..) => {
-                    if line_count > 0 {
-                        // we've already processed a line for this row, 
-                        // so instead of returning the None right now, return the row
-                        // We'll end up returning None on the next call to this function
-                        
-                        // The parser might have left some data in the column_buffer variable since it never encountered a newline.
-                        // Add whatever was collected to the current row
-                        if !self.column_buffer.is_empty() {
-                            self.new_column();
-                        }
-                        // break to return normally
-                        break; 
-                    }
-                    // No more data in input & no data processed on current call to next_row
                     return None;
-                },
-                // Read error encountered, return the error
-                Err(e) => {
-                    return Some(Err(e));
                 }
+                parse(&mut self.parser,Some(input))
+            };
+            self.input_reader.consume(consumed);
+            if done {
+                break;
             }
         }
-
-        return Some(Ok(&self.row_data))
-        
+        Some(self.parser.record_result())
     }    
 }
 
@@ -208,33 +428,31 @@ impl<B: BufRead> Iterator for SimpleCsvReader<B> {
     type Item = Result<Vec<String>>;
     
     fn next(&mut self) -> Option<Result<Vec<String>>> {
-        // The function is written like this in order to avoid having to clone the row vector
-        // Instead we create a new Vec with a capacity of row_data and swap the structure's vec with the new one
-        return match self.next_row() {
-            Some(Ok(..)) => {
-                // 
-                let cap = self.row_data.capacity();
-                let row = replace(&mut self.row_data, Vec::with_capacity(cap));
-                Some(Ok(row))
-            },
-            Some(Err(e)) => {
-                Some(Err(e))
-            },
-            None => {
-                None
-            }
+        if let Err(e) = self.read_headers() {
+            return Some(Err(e));
+        }
+        match self.read_row()? {
+            Ok(..) => Some(self.parser.take_row()),
+            Err(e) => Some(Err(e))
         }
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        return (0,None);
+        (0,None)
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
 mod tests {    
     use super::*;
     use std::default::Default;
+    use crate::dialect::Dialect;
+    use crate::headers::HeaderError;
+    use crate::error::{Error,Position};
+    use crate::record::{ByteRecord,StringRecord};
+    use crate::writer::{NewlineType,SimpleCsvWriter,SimpleCsvWriterOptions};
+    use std::io::{self,BufReader,Read};
 
     #[test]
     fn reader_simple_csv_test() {
@@ -393,7 +611,7 @@ mod tests {
         let bytes = test_string.into_bytes();
         let test_csv_reader = &*bytes;
         let mut csv_options: SimpleCsvReaderOptions = Default::default();
-        csv_options.text_enclosure = '#';
+        csv_options.text_enclosure = Some('#');
         let mut reader = SimpleCsvReader::with_options(test_csv_reader,csv_options);
 
         assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["1".to_string(),"2".to_string(),"3".to_string()]);
@@ -401,6 +619,17 @@ mod tests {
         assert!(reader.next_row().is_none());
     }
 
+    #[test]
+    fn reader_without_text_enclosure() {
+        let test_string = "1\t\"2\t3\"\r\n\"\"\t\"a\"b\"";
+        let csv_options = SimpleCsvReaderOptions { delimiter: '\t', text_enclosure: None, strictness: Strictness::Strict, ..Default::default() };
+        let mut reader = SimpleCsvReader::with_options(test_string.as_bytes(),csv_options);
+
+        assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["1".to_string(),"\"2".to_string(),"3\"".to_string()]);
+        assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["\"\"".to_string(),"\"a\"b\"".to_string()]);
+        assert!(reader.next_row().is_none());
+    }
+
     #[test]
     fn reader_utf8_delimiter() {
 
@@ -417,6 +646,571 @@ mod tests {
     }
 
 
+    #[test]
+    fn reader_headers() {
+        let test_string = "a,b,c\r\n1,2,3\r\n4,5,6".to_string();
+        let bytes = test_string.into_bytes();
+        let test_csv_reader = &*bytes;
+        let csv_options = SimpleCsvReaderOptions { has_headers: true, ..Default::default() };
+        let mut reader = SimpleCsvReader::with_options(test_csv_reader,csv_options);
+
+        assert_eq!(reader.headers().unwrap().unwrap().as_slice(), &*vec!["a".to_string(),"b".to_string(),"c".to_string()]);
+        assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["1".to_string(),"2".to_string(),"3".to_string()]);
+        {
+            let row = reader.next_named_row().unwrap().unwrap();
+            assert_eq!(row.get_by_name("c"), Ok("6"));
+            assert_eq!(row.get(0), Some("4"));
+        }
+        assert!(reader.next_row().is_none());
+    }
+
+    #[test]
+    fn reader_headers_skipped_by_iterator() {
+        let test_string = "a,b,a\r\n1,2,3".to_string();
+        let bytes = test_string.into_bytes();
+        let test_csv_reader = &*bytes;
+        let csv_options = SimpleCsvReaderOptions { has_headers: true, ..Default::default() };
+        let reader = SimpleCsvReader::with_options(test_csv_reader,csv_options);
+
+        let rows: Vec<Vec<String>> = reader.map(|row| row.unwrap()).collect();
+        assert_eq!(rows, vec![vec!["1".to_string(),"2".to_string(),"3".to_string()]]);
+    }
+
+    #[test]
+    fn reader_headers_duplicate_name() {
+        let test_string = "a,b,a\r\n1,2,3".to_string();
+        let bytes = test_string.into_bytes();
+        let test_csv_reader = &*bytes;
+        let csv_options = SimpleCsvReaderOptions { has_headers: true, ..Default::default() };
+        let mut reader = SimpleCsvReader::with_options(test_csv_reader,csv_options);
+
+        let row = reader.next_named_row().unwrap().unwrap();
+        assert_eq!(row.get_by_name("b"), Ok("2"));
+        assert_eq!(row.get_by_name("a"), Err(HeaderError::DuplicateColumn("a".to_string())));
+        assert_eq!(row.get_by_name("z"), Err(HeaderError::MissingColumn("z".to_string())));
+        assert_eq!(row.headers().unwrap().duplicates(), vec!["a"]);
+    }
+
+    #[test]
+    fn reader_no_headers_by_default() {
+        let test_string = "a,b,c\r\n1,2,3".to_string();
+        let bytes = test_string.into_bytes();
+        let test_csv_reader = &*bytes;
+        let mut reader = SimpleCsvReader::new(test_csv_reader);
+
+        assert!(reader.headers().is_none());
+        assert_eq!(reader.next_named_row().unwrap().unwrap().get_by_name("a"), Err(HeaderError::NoHeaders));
+    }
+
+
+    #[test]
+    fn reader_position() {
+        let test_string = "1,2,3\r\n4,\"5\r\n\",6\r\n7,8,9".to_string();
+        let bytes = test_string.into_bytes();
+        let test_csv_reader = &*bytes;
+        let mut reader = SimpleCsvReader::new(test_csv_reader);
+
+        reader.next_row();
+        assert_eq!(reader.position(), Position { byte: 0, line: 1, record: 0 });
+        reader.next_row();
+        assert_eq!(reader.position(), Position { byte: 7, line: 2, record: 1 });
+        reader.next_row();
+        assert_eq!(reader.position(), Position { byte: 18, line: 4, record: 2 });
+    }
+
+    #[test]
+    fn reader_byte_range() {
+        let test_string = "1,2,3\r\n4,\"5\r\n\",6\r\n7,8,9".to_string();
+        let bytes = test_string.into_bytes();
+        let mut reader = SimpleCsvReader::new(&*bytes);
+
+        let mut ranges = Vec::new();
+        while let Some(Ok(..)) = reader.next_record() {
+            ranges.push(reader.byte_range());
+        }
+        assert_eq!(ranges, vec![0..7,7..18,18..23]);
+        assert_eq!(&bytes[7..18], b"4,\"5\r\n\",6\r\n");
+    }
+
+    #[test]
+    fn reader_comments() {
+        let test_string = "\u{00A9}version 2\r\na,b\r\n\u{00A9}skip, \"this\r\n1,\u{00A9}2\r\n\"\u{00A9}\n3\",4\n\u{00A9}end";
+        for &capacity in &[1,2,3,64] {
+            let input = BufReader::with_capacity(capacity,test_string.as_bytes());
+            let csv_options = SimpleCsvReaderOptions { comment: Some('\u{00A9}'), ..Default::default() };
+            let mut reader = SimpleCsvReader::with_options(input,csv_options);
+
+            assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["a".to_string(),"b".to_string()]);
+            assert_eq!(reader.comments(), &["version 2".to_string()]);
+            assert_eq!(reader.position(), Position { byte: 13, line: 2, record: 0 });
+            assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["1".to_string(),"\u{00A9}2".to_string()]);
+            assert_eq!(reader.comments(), &["skip, \"this".to_string()]);
+            assert_eq!(reader.position(), Position { byte: 33, line: 4, record: 1 });
+            // A quoted field that starts with the comment character is data
+            assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["\u{00A9}\n3".to_string(),"4".to_string()]);
+            assert!(reader.comments().is_empty());
+            assert!(reader.next_row().is_none());
+            assert_eq!(reader.comments(), &["end".to_string()]);
+        }
+    }
+
+    #[test]
+    fn reader_comments_after_whitespace() {
+        // Trimmed or not, whitespace before the comment character makes the line a record
+        let test_string = " #a,b\n1,2\n#c\n\t3,4";
+        for capacity in 1..test_string.len() + 1 {
+            let input = BufReader::with_capacity(capacity,test_string.as_bytes());
+            let csv_options = SimpleCsvReaderOptions { comment: Some('#'), trim: Trim::Leading, ..Default::default() };
+            let mut reader = SimpleCsvReader::with_options(input,csv_options);
+
+            assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["#a".to_string(),"b".to_string()], "capacity {}", capacity);
+            assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["1".to_string(),"2".to_string()]);
+            assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["3".to_string(),"4".to_string()]);
+            assert_eq!(reader.comments(), &["c".to_string()]);
+            assert!(reader.next_row().is_none());
+        }
+    }
+
+    #[test]
+    fn reader_trim() {
+        let test_string = " 1 ,\t\" 2 \" , 3\t\r\n \"4\"x ,\t, \"\"\n";
+        let trimmed = |trim| {
+            let csv_options = SimpleCsvReaderOptions { trim, ..Default::default() };
+            SimpleCsvReader::with_options(test_string.as_bytes(),csv_options).map(|row| row.unwrap()).collect::<Vec<_>>()
+        };
+        let rows = |rows: &[&[&str]]| rows.iter().map(|row| row.iter().map(|field| field.to_string()).collect()).collect::<Vec<Vec<String>>>();
+
+        assert_eq!(trimmed(Trim::None), rows(&[&[" 1 ","\t\" 2 \" "," 3\t"],&[" \"4\"x ","\t"," \"\""]]));
+        assert_eq!(trimmed(Trim::Leading), rows(&[&["1 "," 2  ","3\t"],&["4x ","",""]]));
+        assert_eq!(trimmed(Trim::Trailing), rows(&[&[" 1","\t\" 2 \""," 3"],&[" \"4\"x",""," \"\""]]));
+        assert_eq!(trimmed(Trim::Both), rows(&[&["1","2","3"],&["4x","",""]]));
+        assert_eq!(trimmed(Trim::Unquoted), rows(&[&["1"," 2 ","3"],&["4x","",""]]));
+
+        // A tab delimiter is not whitespace
+        let csv_options = SimpleCsvReaderOptions { delimiter: '\t', trim: Trim::Both, ..Default::default() };
+        let mut reader = SimpleCsvReader::with_options(" a \t\t b".as_bytes(),csv_options);
+        assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["a".to_string(),"".to_string(),"b".to_string()]);
+    }
+
+    #[test]
+    fn reader_escape() {
+        let test_string = "1,a\\,b,\"q\\\"x\"\"\",c\\\\d\\tz\nm\\\nn,\\N,end\\";
+        let csv_options = SimpleCsvReaderOptions { escape: Some('\\'), ..Default::default() };
+        for &capacity in &[1,2,1024] {
+            let input = BufReader::with_capacity(capacity,test_string.as_bytes());
+            let mut reader = SimpleCsvReader::with_options(input,csv_options.clone());
+            assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["1".to_string(),"a,b".to_string(),"q\"x\"".to_string(),"c\\d\tz".to_string()]);
+            // An escaped newline is data, and an escape at the end of the input is kept
+            assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["m\nn".to_string(),"N".to_string(),"end\\".to_string()]);
+            assert_eq!(reader.position(), Position { byte: 24, line: 2, record: 1 });
+            assert!(reader.next_row().is_none());
+        }
+
+        // The `\n` escape sequence does not start a new line
+        let csv_options = SimpleCsvReaderOptions { escape: Some('\\'), strictness: Strictness::Strict, ..Default::default() };
+        let mut reader = SimpleCsvReader::with_options("a\\nb\\nc\n1,\"x\"y\n".as_bytes(),csv_options);
+        assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["a\nb\nc".to_string()]);
+        match reader.next_row() {
+            Some(Err(Error::UnexpectedDataAfterQuote { pos })) => assert_eq!(pos, Position { byte: 13, line: 2, record: 1 }),
+            _ => panic!("expected UnexpectedDataAfterQuote")
+        }
+    }
+
+    #[test]
+    fn reader_custom_terminator() {
+        // The terminator starts like the delimiter
+        let test_string = "a||\"b|\n\"||c|\nd\ne||f";
+        let csv_options = SimpleCsvReaderOptions { delimiter_str: Some("||".to_string()), terminator: RecordTerminator::Custom("|\n".to_string()), ..Default::default() };
+        for &capacity in &[1,2,1024] {
+            let input = BufReader::with_capacity(capacity,test_string.as_bytes());
+            let mut reader = SimpleCsvReader::with_options(input,csv_options.clone());
+            assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["a".to_string(),"b|\n".to_string(),"c".to_string()]);
+            assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["d\ne".to_string(),"f".to_string()]);
+            assert_eq!(reader.position(), Position { byte: 13, line: 3, record: 1 });
+            assert!(reader.next_row().is_none());
+        }
+
+        // Only a full CRLF ends a record, also after a comment
+        let csv_options = SimpleCsvReaderOptions { comment: Some('#'), terminator: RecordTerminator::Custom("\r\n".to_string()), ..Default::default() };
+        for &capacity in &[1,2,1024] {
+            let input = BufReader::with_capacity(capacity,"#x\ry\r\n1\n2,3\r\r\n".as_bytes());
+            let mut reader = SimpleCsvReader::with_options(input,csv_options.clone());
+            assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["1\n2".to_string(),"3\r".to_string()]);
+            assert_eq!(reader.comments(), &["x\ry".to_string()]);
+            assert!(reader.next_row().is_none());
+        }
+    }
+
+    #[test]
+    fn reader_record_terminators() {
+        let test_string = "a,b\rc,\"d\re\"\r\nf\ng\r";
+        let read = |terminator: RecordTerminator, capacity| {
+            let csv_options = SimpleCsvReaderOptions { terminator, ..Default::default() };
+            let mut reader = SimpleCsvReader::with_options(BufReader::with_capacity(capacity,test_string.as_bytes()),csv_options);
+            let mut rows = Vec::new();
+            while let Some(row) = reader.next_row() {
+                rows.push(row.unwrap().join("|"));
+            }
+            (rows, reader.newline_type().cloned())
+        };
+        for &capacity in &[1,1024] {
+            assert_eq!(read(RecordTerminator::Newline,capacity), (vec!["a|bc|d\re".to_string(),"f".to_string(),"g".to_string()],Some(NewlineType::WindowsStyle)));
+            assert_eq!(read(RecordTerminator::Lf,capacity), (vec!["a|b\rc|d\re\r".to_string(),"f".to_string(),"g\r".to_string()],Some(NewlineType::UnixStyle)));
+            assert_eq!(read(RecordTerminator::CrLf,capacity), (vec!["a|b\rc|d\re".to_string(),"f\ng\r".to_string()],Some(NewlineType::WindowsStyle)));
+            assert_eq!(read(RecordTerminator::Cr,capacity), (vec!["a|b".to_string(),"c|d\re".to_string(),"\nf\ng".to_string()],Some(NewlineType::Custom("\r".to_string()))));
+            assert_eq!(read(RecordTerminator::Any,capacity), (vec!["a|b".to_string(),"c|d\re".to_string(),"f".to_string(),"g".to_string()],Some(NewlineType::Custom("\r".to_string()))));
+        }
+
+        // A bare return inside quotes starts a new line with `Any` as it does with `Cr`
+        let input = "1,\"a\rb\rc\"\r2,3\r";
+        for terminator in [RecordTerminator::Cr,RecordTerminator::Any].iter() {
+            for &capacity in &[1,1024] {
+                let csv_options = SimpleCsvReaderOptions { terminator: terminator.clone(), ..Default::default() };
+                let mut reader = SimpleCsvReader::with_options(BufReader::with_capacity(capacity,input.as_bytes()),csv_options);
+                assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["1".to_string(),"a\rb\rc".to_string()]);
+                assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["2".to_string(),"3".to_string()]);
+                assert_eq!(reader.position(), Position { byte: 10, line: 4, record: 1 });
+            }
+        }
+
+        // Comment lines end the same way
+        let csv_options = SimpleCsvReaderOptions { comment: Some('#'), terminator: RecordTerminator::Any, ..Default::default() };
+        for &capacity in &[1,1024] {
+            let mut reader = SimpleCsvReader::with_options(BufReader::with_capacity(capacity,"#x\r#y\r\n1\r2".as_bytes()),csv_options.clone());
+            assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["1".to_string()]);
+            assert_eq!(reader.comments(), &["x".to_string(),"y".to_string()]);
+            assert_eq!(reader.position(), Position { byte: 7, line: 3, record: 0 });
+            assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["2".to_string()]);
+            assert_eq!(reader.position(), Position { byte: 9, line: 4, record: 1 });
+            assert!(reader.next_row().is_none());
+        }
+    }
+
+    #[test]
+    fn reader_nullable_row() {
+        let test_string = "1,,\"\",NULL,\"NULL\",NA,NAN";
+        let csv_options = SimpleCsvReaderOptions { nulls: vec!["".to_string(),"NULL".to_string(),"NA".to_string()], ..Default::default() };
+        let mut reader = SimpleCsvReader::with_options(test_string.as_bytes(),csv_options);
+        let row = reader.next_nullable_row().unwrap().unwrap().to_vec();
+        let string = |value: &str| Some(value.to_string());
+        assert_eq!(row, vec![string("1"),None,string(""),None,string("NULL"),None,string("NAN")]);
+        assert_eq!(reader.quoted_fields(), &[false,false,true,false,true,false,false]);
+
+        // Without `nulls` every field is `Some`
+        let mut reader = SimpleCsvReader::new(test_string.as_bytes());
+        assert!(reader.next_nullable_row().unwrap().unwrap().iter().all(|field| field.is_some()));
+    }
+
+    #[test]
+    fn reader_nullable_round_trip() {
+        let string = |value: &str| Some(value.to_string());
+        let rows = vec![
+            vec![string("1"),None,string("")],
+            vec![string("2"),string(""),None],
+            vec![None,None]];
+        // The last row ends the input without a newline, so its last field only shows from the delimiter before it
+        let mut writer = SimpleCsvWriter::new(Vec::new());
+        for row in rows.iter() {
+            writer.write_nullable(row).unwrap();
+        }
+        let bytes = writer.as_inner();
+        assert_eq!(bytes, b"1,,\"\"\n2,\"\",\n,");
+
+        let csv_options = SimpleCsvReaderOptions { nulls: vec!["".to_string()], ..Default::default() };
+        let mut reader = SimpleCsvReader::with_options(&*bytes,csv_options.clone());
+        let mut read = Vec::new();
+        while let Some(row) = reader.next_nullable_row() {
+            read.push(row.unwrap().to_vec());
+        }
+        assert_eq!(read, rows);
+
+        let mut reader = SimpleCsvReader::with_options(&b"1,,\"\","[..],csv_options);
+        assert_eq!(reader.next_nullable_row().unwrap().unwrap(), &[string("1"),None,string(""),None][..]);
+    }
+
+    #[test]
+    fn reader_column_count() {
+        let test_string = "id,name\n1,a,x\n2\n3,c";
+        let read = |column_count: ColumnCount, has_headers: bool| {
+            let csv_options = SimpleCsvReaderOptions { column_count, has_headers, ..Default::default() };
+            SimpleCsvReader::with_options(test_string.as_bytes(),csv_options)
+                .map(|row| row.map(|row| row.join("|")).map_err(|e| e.to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(read(ColumnCount::Flexible,true), vec![Ok("1|a|x".to_string()),Ok("2".to_string()),Ok("3|c".to_string())]);
+        assert_eq!(read(ColumnCount::Exact,true), vec![
+            Err("expected 2 fields but found 3 at line 2, record 1, byte 8".to_string()),
+            Err("expected 2 fields but found 1 at line 3, record 2, byte 14".to_string()),
+            Ok("3|c".to_string())]);
+        assert_eq!(read(ColumnCount::Pad,true)[1..], [Ok("2|".to_string()),Ok("3|c".to_string())]);
+        assert_eq!(read(ColumnCount::Truncate,true)[..1], [Ok("1|a".to_string())]);
+        assert!(read(ColumnCount::Truncate,true)[1].is_err());
+
+        // Without headers the first row sets the count
+        assert_eq!(read(ColumnCount::Pad,false)[..3], [Ok("id|name".to_string()),Err("expected 2 fields but found 3 at line 2, record 1, byte 8".to_string()),Ok("2|".to_string())]);
+    }
+
+    #[test]
+    fn reader_column_count_pads_with_null() {
+        let string = |value: &str| Some(value.to_string());
+        let csv_options = SimpleCsvReaderOptions { column_count: ColumnCount::Pad, ..Dialect::postgresql_text().into() };
+        let mut reader = SimpleCsvReader::with_options(&b"1\t\\N\tx\n2\t\n3"[..],csv_options);
+        assert_eq!(reader.next_nullable_row().unwrap().unwrap(), &[string("1"),None,string("x")][..]);
+        assert_eq!(reader.next_nullable_row().unwrap().unwrap(), &[string("2"),string(""),None][..]);
+        assert_eq!(reader.next_nullable_row().unwrap().unwrap(), &[string("3"),None,None][..]);
+        assert!(reader.next_nullable_row().is_none());
+    }
+
+    #[test]
+    fn reader_column_count_round_trip() {
+        let row = |fields: &[&str]| fields.iter().map(|field| field.to_string()).collect::<Vec<_>>();
+        let csv_options = SimpleCsvReaderOptions { column_count: ColumnCount::Exact, ..Default::default() };
+        let read = |bytes: &[u8]| {
+            SimpleCsvReader::with_options(bytes,csv_options.clone())
+                .map(|row| row.unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(read(b"a,b\n1,"), vec![row(&["a","b"]),row(&["1",""])]);
+
+        // What the writer pads or truncates reads back with the same number of fields, even at the end of the input
+        let write = |column_count: ColumnCount, rows: &[Vec<String>]| {
+            let writer_options = SimpleCsvWriterOptions { column_count, ..Default::default() };
+            let mut writer = SimpleCsvWriter::with_options(Vec::new(),writer_options);
+            writer.write_all(rows).unwrap();
+            writer.as_inner()
+        };
+        let bytes = write(ColumnCount::Pad,&[row(&["a","b","c"]),row(&["d"])]);
+        assert_eq!(read(&bytes), vec![row(&["a","b","c"]),row(&["d","",""])]);
+        let bytes = write(ColumnCount::Truncate,&[row(&["a","b"]),row(&["c","","d"])]);
+        assert_eq!(read(&bytes), vec![row(&["a","b"]),row(&["c",""])]);
+    }
+
+    #[test]
+    fn reader_reads_custom_newline_from_writer() {
+        let rows = vec![vec!["1".to_string(),"a\x1eb".to_string()],vec!["2\n".to_string(),"\r".to_string()]];
+        let writer_options = SimpleCsvWriterOptions { newline_type: NewlineType::Custom("\x1e".to_string()), ..Default::default() };
+        let mut writer = SimpleCsvWriter::with_options(Vec::new(),writer_options);
+        writer.write_all(&rows).unwrap();
+        let bytes = writer.as_inner();
+
+        let csv_options = SimpleCsvReaderOptions { terminator: RecordTerminator::Custom("\x1e".to_string()), ..Default::default() };
+        let reader = SimpleCsvReader::with_options(&*bytes,csv_options);
+        assert_eq!(reader.map(|row| row.unwrap()).collect::<Vec<_>>(), rows);
+    }
+
+    struct FailingReader {
+        data: &'static [u8]
+    }
+
+    impl Read for FailingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.data.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::ConnectionReset,"reset"));
+            }
+            let n = self.data.read(buf)?;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn reader_io_error_position() {
+        let input = BufReader::new(FailingReader { data: b"1,2,3\r\n4,5" });
+        let mut reader = SimpleCsvReader::new(input);
+
+        assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["1".to_string(),"2".to_string(),"3".to_string()]);
+        match reader.next_row() {
+            Some(Err(Error::Io { ref error, pos })) => {
+                assert_eq!(error.kind(), io::ErrorKind::ConnectionReset);
+                assert_eq!(pos, Position { byte: 10, line: 2, record: 1 });
+            },
+            _ => panic!("expected an I/O error")
+        }
+    }
+
+
+    #[test]
+    fn reader_strict_data_after_quoted_field() {
+        let test_string = "1,2,3\r\n4,5,\"6\"data_after_quoted_field\r\n7,8,9".to_string();
+        let bytes = test_string.into_bytes();
+        let test_csv_reader = &*bytes;
+        let csv_options = SimpleCsvReaderOptions { strictness: Strictness::Strict, ..Default::default() };
+        let mut reader = SimpleCsvReader::with_options(test_csv_reader,csv_options);
+
+        assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["1".to_string(),"2".to_string(),"3".to_string()]);
+        match reader.next_row() {
+            Some(Err(Error::UnexpectedDataAfterQuote { pos })) => assert_eq!(pos, Position { byte: 14, line: 2, record: 1 }),
+            _ => panic!("expected UnexpectedDataAfterQuote")
+        }
+        // The reader resumes at the next row
+        assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["7".to_string(),"8".to_string(),"9".to_string()]);
+        assert!(reader.next_row().is_none());
+    }
+
+    #[test]
+    fn reader_strict_eof_in_quoted_field() {
+        let test_string = "1,2,3\r\n4,5,\"6".to_string();
+        let bytes = test_string.into_bytes();
+        let test_csv_reader = &*bytes;
+        let csv_options = SimpleCsvReaderOptions { strictness: Strictness::Strict, ..Default::default() };
+        let mut reader = SimpleCsvReader::with_options(test_csv_reader,csv_options);
+
+        assert!(reader.next_row().unwrap().is_ok());
+        match reader.next_row() {
+            Some(Err(Error::UnterminatedQuote { pos })) => assert_eq!(pos, Position { byte: 11, line: 2, record: 1 }),
+            _ => panic!("expected UnterminatedQuote")
+        }
+        assert!(reader.next_row().is_none());
+    }
+
+    #[test]
+    fn reader_strict_stray_characters() {
+        let csv_options = SimpleCsvReaderOptions { strictness: Strictness::Strict, ..Default::default() };
+
+        let bytes = "1,2\r3\r\n".to_string().into_bytes();
+        let mut reader = SimpleCsvReader::with_options(&*bytes,csv_options.clone());
+        match reader.next_row() {
+            Some(Err(Error::StrayCarriageReturn { pos })) => assert_eq!(pos.byte, 3),
+            _ => panic!("expected StrayCarriageReturn")
+        }
+
+        let bytes = "1,2\",3\r\n".to_string().into_bytes();
+        let mut reader = SimpleCsvReader::with_options(&*bytes,csv_options.clone());
+        match reader.next_row() {
+            Some(Err(Error::UnexpectedQuote { pos })) => assert_eq!(pos.byte, 3),
+            _ => panic!("expected UnexpectedQuote")
+        }
+
+        let mut bytes = "1,2,3\r\n4,5,6".to_string().into_bytes();
+        bytes.insert(10, 0xff);
+        let mut reader = SimpleCsvReader::with_options(&*bytes,csv_options.clone());
+        assert!(reader.next_row().unwrap().is_ok());
+        match reader.next_row() {
+            Some(Err(Error::Utf8 { pos })) => assert_eq!(pos, Position { byte: 10, line: 2, record: 1 }),
+            _ => panic!("expected Utf8")
+        }
+
+        // The position counts what was left out of the field before the invalid byte
+        let utf8_position = |bytes: &[u8], trim: Trim| {
+            let csv_options = SimpleCsvReaderOptions { trim, escape: Some('\\'), ..csv_options.clone() };
+            match SimpleCsvReader::with_options(bytes,csv_options).next_row() {
+                Some(Err(Error::Utf8 { pos })) => pos,
+                _ => panic!("expected Utf8")
+            }
+        };
+        assert_eq!(utf8_position(b"1,\"ab\xff\"",Trim::None).byte, 5);
+        assert_eq!(utf8_position(b"1,\"a\"\"\xff\"",Trim::None).byte, 6);
+        assert_eq!(utf8_position(b"1,a\\,\xff",Trim::None).byte, 5);
+        assert_eq!(utf8_position(b"1,a\\\xff",Trim::None).byte, 4);
+        assert_eq!(utf8_position(b"1, \" a\xff\"",Trim::Both).byte, 6);
+        assert_eq!(utf8_position(b"1,  a\xff",Trim::Leading).byte, 5);
+        assert_eq!(utf8_position(b"1,\"a\nb\xff\"",Trim::None), Position { byte: 6, line: 2, record: 0 });
+
+        // Returns inside quoted fields and CRLF line endings are fine
+        let bytes = "1,\"2\r\",3\r\n".to_string().into_bytes();
+        let mut reader = SimpleCsvReader::with_options(&*bytes,csv_options);
+        assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["1".to_string(),"2\r".to_string(),"3".to_string()]);
+    }
+
+    #[test]
+    fn reader_warn_collects_violations() {
+        let test_string = "1,2\",\"3\"4\r\n5,6,7".to_string();
+        let bytes = test_string.into_bytes();
+        let test_csv_reader = &*bytes;
+        let csv_options = SimpleCsvReaderOptions { strictness: Strictness::Warn, ..Default::default() };
+        let mut reader = SimpleCsvReader::with_options(test_csv_reader,csv_options);
+
+        assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["1".to_string(),"2\"".to_string(),"34".to_string()]);
+        assert_eq!(reader.warnings().len(), 2);
+        match reader.warnings()[1] {
+            Error::UnexpectedDataAfterQuote { pos } => assert_eq!(pos.byte, 8),
+            _ => panic!("expected UnexpectedDataAfterQuote")
+        }
+        assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["5".to_string(),"6".to_string(),"7".to_string()]);
+        assert!(reader.warnings().is_empty());
+
+        // Every Latin-1 field of a row gets a warning at its own byte
+        let csv_options = SimpleCsvReaderOptions { strictness: Strictness::Warn, ..Default::default() };
+        let mut reader = SimpleCsvReader::with_options(&b"caf\xe9,na\xefve,ok,\"\xe0\"\n"[..],csv_options);
+        assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["caf\u{FFFD}".to_string(),"na\u{FFFD}ve".to_string(),"ok".to_string(),"\u{FFFD}".to_string()]);
+        let bytes: Vec<u64> = reader.warnings().iter().map(|warning| match *warning {
+            Error::Utf8 { pos } => pos.byte,
+            _ => panic!("expected Utf8")
+        }).collect();
+        assert_eq!(bytes, vec![3,7,15]);
+    }
+
+
+    #[test]
+    fn reader_byte_record() {
+        // "caf\xe9" is Latin-1, not UTF-8
+        let bytes = b"1,caf\xe9,\"3\"\"\"\r\n4,\xa9,6".to_vec();
+        let test_csv_reader = &*bytes;
+        let mut reader = SimpleCsvReader::new(test_csv_reader);
+
+        assert_eq!(*reader.next_byte_record().unwrap().unwrap(), ByteRecord::from(&[&b"1"[..],b"caf\xe9",b"3\""][..]));
+        assert_eq!(*reader.next_byte_record().unwrap().unwrap(), ByteRecord::from(&[&b"4"[..],b"\xa9",b"6"][..]));
+        assert!(reader.next_byte_record().is_none());
+    }
+
+    #[test]
+    fn reader_byte_record_utf8_delimiter() {
+        let test_string = "1\u{00A9}\u{00A9}2\u{00A9}\"\u{00A9}\"\r\n".to_string();
+        let bytes = test_string.into_bytes();
+        let test_csv_reader = &*bytes;
+        let csv_options = SimpleCsvReaderOptions { delimiter: '\u{00A9}', ..Default::default() };
+        let mut reader = SimpleCsvReader::with_options(test_csv_reader,csv_options);
+
+        let record = reader.next_byte_record().unwrap().unwrap();
+        assert_eq!(record.len(), 4);
+        assert_eq!(&record[1], b"");
+        assert_eq!(&record[3], "\u{00A9}".as_bytes());
+    }
+
+
+    #[test]
+    fn reader_next_record() {
+        let test_string = "1,\"2\"\"\",3\r\n4,5\r\n".to_string();
+        let mut bytes = test_string.into_bytes();
+        bytes.push(0xff);
+        let test_csv_reader = &*bytes;
+        let mut reader = SimpleCsvReader::new(test_csv_reader);
+
+        assert_eq!(*reader.next_record().unwrap().unwrap(), StringRecord::from(&["1","2\"","3"][..]));
+        {
+            let record = reader.next_record().unwrap().unwrap();
+            assert_eq!(record.iter().collect::<Vec<&str>>(), vec!["4","5"]);
+            assert_eq!(&record[1], "5");
+        }
+        assert_eq!(reader.next_record().unwrap().unwrap().get(0), Some("\u{FFFD}"));
+        assert!(reader.next_record().is_none());
+    }
+
+    #[test]
+    fn reader_tokens_split_across_buffers() {
+        let test_string = "1\u{00A9}\u{00A9}\"2\u{00A9}\"\"\"\r\n\r\n\"4\r\n\"\u{00A9}5\r\r\n6".to_string();
+        let bytes = test_string.into_bytes();
+        let csv_options = SimpleCsvReaderOptions { delimiter: '\u{00A9}', strictness: Strictness::Warn, ..Default::default() };
+
+        // Reading one byte at a time splits every delimiter and CRLF across two buffers
+        let mut expected = SimpleCsvReader::with_options(&*bytes,csv_options.clone());
+        let mut reader = SimpleCsvReader::with_options(BufReader::with_capacity(1,&*bytes),csv_options.clone());
+        while let Some(row) = expected.next_row() {
+            assert_eq!(reader.next_row().unwrap().unwrap(), row.unwrap());
+            assert_eq!(reader.position(), expected.position());
+            assert_eq!(reader.warnings().len(), expected.warnings().len());
+        }
+        assert!(reader.next_row().is_none());
+
+        let mut reader = SimpleCsvReader::with_options(BufReader::with_capacity(1,&*bytes),csv_options);
+        assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["1".to_string(),"".to_string(),"2\u{00A9}\"".to_string()]);
+        assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["".to_string()]);
+        assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["4\r\n".to_string(),"5".to_string()]);
+        assert_eq!(reader.warnings().len(), 1);
+        assert_eq!(reader.next_row().unwrap().unwrap(), &*vec!["6".to_string()]);
+        assert_eq!(reader.position(), Position { byte: 27, line: 5, record: 3 });
+    }
+
+
 }
 
 #[cfg(feature="nightly")]
@@ -483,6 +1277,61 @@ mod bench {
     }
 
 
+    #[bench]
+    fn reader_bench_throughput_record(b: &mut Bencher) {
+        let num_rows = 10000;
+        let seed_string = "1,\"2\",3,4,\"5\",6\r\n";
+        let total_bytes = seed_string.len() * num_rows;
+        
+        let mut test_string = String::with_capacity(total_bytes);
+        
+        for _ in (0..num_rows) {
+            test_string.push_str(seed_string);
+        }
+        
+        let bytes = test_string.into_bytes();
+        
+        
+        b.bytes = total_bytes as u64;
+        b.iter(|| {
+            let r = &*bytes;
+            let mut x=0;
+            let mut reader = SimpleCsvReader::new(r);
+            while let Some(Ok(_)) = reader.next_record() {
+                x+=1;
+            }
+            assert_eq!(x,num_rows);
+        });
+    }
+
+    #[bench]
+    fn reader_bench_throughput_long_columns_record(b: &mut Bencher) {
+        let num_rows = 10000;
+        let seed_string = "1222222211112,\"231231231231\",3312312312312312312,4312312312312312323123132312312313,\"53123123123123123123123213213\",6233123123123123132\r\n";
+        let total_bytes = seed_string.len() * num_rows;
+        
+        let mut test_string = String::with_capacity(total_bytes);
+        
+        for _ in (0..num_rows) {
+            test_string.push_str(seed_string);
+        }
+        
+        let bytes = test_string.into_bytes();
+        
+        
+        b.bytes = total_bytes as u64;
+        b.iter(|| {
+            let r = &*bytes;
+            let mut x=0;
+            let mut reader = SimpleCsvReader::new(r);
+            while let Some(Ok(_)) = reader.next_record() {
+                x+=1;
+            }
+            assert_eq!(x,num_rows);
+        });
+    }
+
+
     #[bench]
     fn reader_bench_throughput_iter(b: &mut Bencher) {
         let num_rows = 10000;
@@ -0,0 +1,305 @@
+use std::ops::Index;
+use std::str;
+
+/// A row of raw, undecoded fields as returned by `SimpleCsvReader::next_byte_record`.
+/// Quotes have been removed and escaped quotes unescaped, but no UTF-8 decoding takes place.
+///
+/// All fields are stored back to back in a single buffer, along with the offset where each field ends.
+/// Clearing a record keeps both allocations, so a record that is reused for every row
+/// stops allocating once it has seen its longest row.
+#[derive(Clone,Debug,Default,PartialEq,Eq)]
+pub struct ByteRecord {
+    data: Vec<u8>,
+    ends: Vec<usize>
+}
+
+impl ByteRecord {
+    pub fn new() -> ByteRecord {
+        ByteRecord {
+            data: Vec::new(),
+            ends: Vec::new()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        let end = *self.ends.get(index)?;
+        Some(&self.data[self.start_of(index)..end])
+    }
+
+    pub fn iter(&self) -> ByteRecordIter<'_> {
+        ByteRecordIter {
+            record: self,
+            index: 0
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.ends.clear();
+    }
+
+    pub fn push_field(&mut self, field: &[u8]) {
+        self.data.extend_from_slice(field);
+        self.end_field();
+    }
+
+    // Appends to the field currently being built
+    #[inline]
+    pub(crate) fn extend_field(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    #[inline]
+    pub(crate) fn push_byte(&mut self, byte: u8) {
+        self.data.push(byte);
+    }
+
+    #[inline]
+    pub(crate) fn end_field(&mut self) {
+        self.ends.push(self.data.len());
+    }
+
+    // Removes the bytes for which `trim` is true from the start and end of the field currently being built
+    pub(crate) fn trim_partial<F: Fn(u8) -> bool>(&mut self, start: bool, end: bool, trim: F) {
+        let field_start = self.ends.last().cloned().unwrap_or(0);
+        if end {
+            while self.data.len() > field_start && trim(self.data[self.data.len() - 1]) {
+                self.data.pop();
+            }
+        }
+        if start {
+            let leading = self.data[field_start..].iter().take_while(|&&b| trim(b)).count();
+            self.data.drain(field_start..field_start + leading);
+        }
+    }
+
+    // Keeps only the first `len` fields
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.ends.truncate(len);
+        self.data.truncate(self.ends.last().cloned().unwrap_or(0));
+    }
+
+    // Number of bytes added since the last completed field
+    pub(crate) fn partial_len(&self) -> usize {
+        self.data.len() - self.ends.last().cloned().unwrap_or(0)
+    }
+
+    // The bytes added since the last completed field
+    pub(crate) fn partial(&self) -> &[u8] {
+        &self.data[self.ends.last().cloned().unwrap_or(0)..]
+    }
+
+    fn start_of(&self, index: usize) -> usize {
+        if index == 0 { 0 } else { self.ends[index - 1] }
+    }
+
+    // Indexes of the fields that are not valid UTF-8
+    pub(crate) fn invalid_fields(&self) -> Vec<usize> {
+        if self.data.is_ascii() {
+            return Vec::new();
+        }
+        match str::from_utf8(&self.data) {
+            // The buffer as a whole can be valid while a multi-byte character straddles two fields
+            Ok(data) => (0..self.len())
+                .filter(|&index| !data.is_char_boundary(self.start_of(index)) || !data.is_char_boundary(self.ends[index]))
+                .collect(),
+            Err(..) => (0..self.len()).filter(|&index| str::from_utf8(&self[index]).is_err()).collect()
+        }
+    }
+}
+
+impl Index<usize> for ByteRecord {
+    type Output = [u8];
+
+    fn index(&self, index: usize) -> &[u8] {
+        &self.data[self.start_of(index)..self.ends[index]]
+    }
+}
+
+impl<'a, T: AsRef<[u8]>> From<&'a [T]> for ByteRecord {
+    fn from(fields: &'a [T]) -> ByteRecord {
+        let mut record = ByteRecord::new();
+        for field in fields {
+            record.push_field(field.as_ref());
+        }
+        record
+    }
+}
+
+pub struct ByteRecordIter<'a> {
+    record: &'a ByteRecord,
+    index: usize
+}
+
+impl<'a> Iterator for ByteRecordIter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        let field = self.record.get(self.index)?;
+        self.index += 1;
+        Some(field)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.record.len() - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
+/// A row of UTF-8 fields as returned by `SimpleCsvReader::next_record`.
+/// Fields are borrowed as `&str` straight out of the record's single buffer,
+/// so reading rows into a reused `StringRecord` does not allocate per field.
+#[derive(Clone,Debug,Default,PartialEq,Eq)]
+pub struct StringRecord {
+    // Always valid UTF-8 on every field when handed out.
+    // The reader parses straight into these bytes and validates them before returning the record.
+    bytes: ByteRecord
+}
+
+impl StringRecord {
+    pub fn new() -> StringRecord {
+        StringRecord {
+            bytes: ByteRecord::new()
+        }
+    }
+
+    /// Fails with the indexes of the invalid fields if any field is not valid UTF-8
+    pub fn from_byte_record(record: ByteRecord) -> Result<StringRecord,(ByteRecord,Vec<usize>)> {
+        let invalid = record.invalid_fields();
+        if invalid.is_empty() {
+            Ok(StringRecord { bytes: record })
+        } else {
+            Err((record,invalid))
+        }
+    }
+
+    /// Replaces invalid UTF-8 in any field with U+FFFD
+    pub fn from_byte_record_lossy(mut record: ByteRecord) -> StringRecord {
+        make_lossy(&mut record);
+        StringRecord { bytes: record }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.bytes.get(index).map(|field| unsafe { str::from_utf8_unchecked(field) })
+    }
+
+    pub fn iter(&self) -> StringRecordIter<'_> {
+        StringRecordIter {
+            fields: self.bytes.iter()
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.bytes.clear();
+    }
+
+    pub fn push_field(&mut self, field: &str) {
+        self.bytes.push_field(field.as_bytes());
+    }
+
+    pub fn as_byte_record(&self) -> &ByteRecord {
+        &self.bytes
+    }
+
+    pub fn into_byte_record(self) -> ByteRecord {
+        self.bytes
+    }
+
+    // Gives the reader direct access to the bytes it parses into
+    pub(crate) fn bytes_mut(&mut self) -> &mut ByteRecord {
+        &mut self.bytes
+    }
+}
+
+// Rebuilds `record` with every field decoded lossily
+pub(crate) fn make_lossy(record: &mut ByteRecord) {
+    let mut lossy = ByteRecord::new();
+    for field in record.iter() {
+        lossy.push_field(String::from_utf8_lossy(field).as_bytes());
+    }
+    *record = lossy;
+}
+
+impl Index<usize> for StringRecord {
+    type Output = str;
+
+    fn index(&self, index: usize) -> &str {
+        unsafe { str::from_utf8_unchecked(&self.bytes[index]) }
+    }
+}
+
+impl<'a, T: AsRef<str>> From<&'a [T]> for StringRecord {
+    fn from(fields: &'a [T]) -> StringRecord {
+        let mut record = StringRecord::new();
+        for field in fields {
+            record.push_field(field.as_ref());
+        }
+        record
+    }
+}
+
+pub struct StringRecordIter<'a> {
+    fields: ByteRecordIter<'a>
+}
+
+impl<'a> Iterator for StringRecordIter<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.fields.next().map(|field| unsafe { str::from_utf8_unchecked(field) })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.fields.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_reuses_buffers() {
+        let mut record = ByteRecord::from(&["abc","","de"][..]);
+        assert_eq!(record.len(), 3);
+        assert_eq!(&record[1], b"");
+        assert_eq!(&record[2], b"de");
+        record.clear();
+        assert!(record.is_empty());
+        assert_eq!(record.get(0), None);
+        record.push_field(b"x");
+        assert_eq!(record.iter().collect::<Vec<&[u8]>>(), vec![&b"x"[..]]);
+        assert_eq!(record, ByteRecord::from(&[b"x"][..]));
+    }
+
+    #[test]
+    fn record_string_from_bytes() {
+        let record = StringRecord::from_byte_record(ByteRecord::from(&["a","\u{00A9}"][..])).unwrap();
+        assert_eq!(record.iter().collect::<Vec<&str>>(), vec!["a","\u{00A9}"]);
+
+        // A character split over two fields is invalid in both
+        let split = ByteRecord::from(&[&b"a\xc2"[..],b"\xa9",b"b"][..]);
+        let (split, invalid) = StringRecord::from_byte_record(split).unwrap_err();
+        assert_eq!(invalid, vec![0,1]);
+        let record = StringRecord::from_byte_record_lossy(split);
+        assert_eq!(&record[0], "a\u{FFFD}");
+        assert_eq!(&record[1], "\u{FFFD}");
+        assert_eq!(record.get(2), Some("b"));
+    }
+}
@@ -0,0 +1,206 @@
+/// Finds the bytes that end a run of ordinary data in a field:
+/// the first byte of the delimiter and of the quote and the bytes that start a line ending in an unquoted field,
+/// and only the quote and the bytes that start a line counted inside quotes in a quoted one. The first byte of the escape character, if any, ends both.
+/// Line endings start with `\n` or `\r`, or with the first byte of a custom terminator.
+///
+/// Without the `simd` feature, every byte is looked up in a table.
+/// With it, x86_64 looks up the first 16 bytes of a run in the table and then compares 32 bytes at a time with AVX2
+/// when the CPU supports it, or 16 at a time with SSE2 otherwise. Other architectures always use the table,
+/// and so do unquoted fields when there is an escape character, as that makes more needles than a compare takes.
+pub(crate) struct Scanner {
+    field: [bool; 256],
+    quoted: [bool; 256],
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    field_needles: [u8; 4],
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    quoted_needles: [u8; 4],
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    field_vector: bool,
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    avx2: bool
+}
+
+// Runs shorter than this are not worth a vector compare
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+const SCALAR_PREFIX: usize = 16;
+
+impl Scanner {
+    /// `line_start` holds one or two bytes that may start a line ending.
+    /// `quoted_line_start` holds one or two of them that also end runs inside quotes, so that their lines are counted
+    pub(crate) fn new(delimiter: u8, text_enclosure: Option<u8>, line_start: &[u8], quoted_line_start: &[u8], escape: Option<u8>) -> Scanner {
+        let mut field = [false; 256];
+        let mut quoted = [false; 256];
+        for &b in [delimiter].iter().chain(line_start) {
+            field[b as usize] = true;
+        }
+        for &b in quoted_line_start {
+            quoted[b as usize] = true;
+        }
+        for &b in text_enclosure.iter().chain(&escape) {
+            field[b as usize] = true;
+            quoted[b as usize] = true;
+        }
+        // Without a quote, repeating another needle in its place does not change the result
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        let text_enclosure = text_enclosure.unwrap_or(delimiter);
+        Scanner {
+            field,
+            quoted,
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            field_needles: [delimiter, text_enclosure, line_start[0], line_start[line_start.len() - 1]],
+            // Repeating a needle does not change the result
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            quoted_needles: [text_enclosure, quoted_line_start[0], escape.unwrap_or(text_enclosure), quoted_line_start[quoted_line_start.len() - 1]],
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            field_vector: escape.is_none(),
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            avx2: is_x86_feature_detected!("avx2")
+        }
+    }
+
+    /// Length of the run of data at the start of `input`, up to the first byte that may end it
+    #[inline]
+    pub(crate) fn run_len(&self, input: &[u8], in_quotes: bool) -> usize {
+        let table = if in_quotes { &self.quoted } else { &self.field };
+
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        {
+            if input.len() > SCALAR_PREFIX && (in_quotes || self.field_vector) {
+                if let Some(run) = input[..SCALAR_PREFIX].iter().position(|&b| table[b as usize]) {
+                    return run;
+                }
+                let needles = if in_quotes { &self.quoted_needles } else { &self.field_needles };
+                let rest = &input[SCALAR_PREFIX..];
+                let scanned = unsafe {
+                    if self.avx2 {
+                        x86::find_avx2(needles,rest)
+                    } else {
+                        x86::find_sse2(needles,rest)
+                    }
+                };
+                return SCALAR_PREFIX + match scanned {
+                    Ok(run) => run,
+                    Err(checked) => checked + rest[checked..].iter().position(|&b| table[b as usize]).unwrap_or(rest.len() - checked)
+                };
+            }
+        }
+
+        input.iter().position(|&b| table[b as usize]).unwrap_or(input.len())
+    }
+}
+
+// Both functions return Ok with the offset of the first needle,
+// or Err with the offset of the first byte that was not checked because too few bytes were left to fill a vector.
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn find_avx2(needles: &[u8; 4], haystack: &[u8]) -> Result<usize,usize> {
+        let n0 = _mm256_set1_epi8(needles[0] as i8);
+        let n1 = _mm256_set1_epi8(needles[1] as i8);
+        let n2 = _mm256_set1_epi8(needles[2] as i8);
+        let n3 = _mm256_set1_epi8(needles[3] as i8);
+        let mut offset = 0;
+        while offset + 32 <= haystack.len() {
+            let chunk = _mm256_loadu_si256(haystack.as_ptr().add(offset) as *const __m256i);
+            let matches = _mm256_or_si256(
+                _mm256_or_si256(_mm256_cmpeq_epi8(chunk,n0),_mm256_cmpeq_epi8(chunk,n1)),
+                _mm256_or_si256(_mm256_cmpeq_epi8(chunk,n2),_mm256_cmpeq_epi8(chunk,n3)));
+            let mask = _mm256_movemask_epi8(matches) as u32;
+            if mask != 0 {
+                return Ok(offset + mask.trailing_zeros() as usize);
+            }
+            offset += 32;
+        }
+        // Finish with SSE2 if at least 16 bytes are left
+        match find_sse2(needles,&haystack[offset..]) {
+            Ok(index) => Ok(offset + index),
+            Err(checked) => Err(offset + checked)
+        }
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn find_sse2(needles: &[u8; 4], haystack: &[u8]) -> Result<usize,usize> {
+        let n0 = _mm_set1_epi8(needles[0] as i8);
+        let n1 = _mm_set1_epi8(needles[1] as i8);
+        let n2 = _mm_set1_epi8(needles[2] as i8);
+        let n3 = _mm_set1_epi8(needles[3] as i8);
+        let mut offset = 0;
+        while offset + 16 <= haystack.len() {
+            let chunk = _mm_loadu_si128(haystack.as_ptr().add(offset) as *const __m128i);
+            let matches = _mm_or_si128(
+                _mm_or_si128(_mm_cmpeq_epi8(chunk,n0),_mm_cmpeq_epi8(chunk,n1)),
+                _mm_or_si128(_mm_cmpeq_epi8(chunk,n2),_mm_cmpeq_epi8(chunk,n3)));
+            let mask = _mm_movemask_epi8(matches) as u32;
+            if mask != 0 {
+                return Ok(offset + mask.trailing_zeros() as usize);
+            }
+            offset += 16;
+        }
+        Err(offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every implementation this machine can run
+    fn scanners(delimiter: u8, text_enclosure: u8, escape: Option<u8>) -> Vec<Scanner> {
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        {
+            let sse2 = Scanner { avx2: false, ..Scanner::new(delimiter,Some(text_enclosure),b"\n\r",b"\n",escape) };
+            vec![Scanner::new(delimiter,Some(text_enclosure),b"\n\r",b"\n",escape),sse2]
+        }
+        #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+        {
+            vec![Scanner::new(delimiter,Some(text_enclosure),b"\n\r",b"\n",escape)]
+        }
+    }
+
+    #[test]
+    fn scan_run_ends_at_every_offset() {
+        for scanner in scanners(b',',b'"',None) {
+            for len in 0..150 {
+                for target in 0..len {
+                    let mut input = vec![b'a'; len];
+                    input[target] = b'\r';
+                    if target + 1 < len {
+                        input[target + 1] = b'"';
+                    }
+                    assert_eq!(scanner.run_len(&input,false), target);
+                    // A return is data inside quotes
+                    assert_eq!(scanner.run_len(&input,true), target + 1);
+                }
+                assert_eq!(scanner.run_len(&vec![b'a'; len],false), len);
+            }
+        }
+    }
+
+    #[test]
+    fn scan_high_bytes() {
+        // Bytes above 0x7f are negative as i8 and must still compare equal
+        for scanner in scanners(0xc2,b'"',None) {
+            let mut input = "\u{00A9}".repeat(80).into_bytes();
+            assert_eq!(scanner.run_len(&input[1..],false), 1);
+            assert_eq!(scanner.run_len(&input,true), input.len());
+            for b in input.iter_mut() {
+                *b = 0xa9;
+            }
+            assert_eq!(scanner.run_len(&input,false), input.len());
+        }
+    }
+
+    #[test]
+    fn scan_escape() {
+        for scanner in scanners(b',',b'"',Some(b'\\')) {
+            for len in 0..80 {
+                let mut input = vec![b'a'; len + 1];
+                input[len] = b'\\';
+                assert_eq!(scanner.run_len(&input,false), len);
+                assert_eq!(scanner.run_len(&input,true), len);
+            }
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::{HashMap,HashSet};
+use std::io::{self,BufRead,Chain,Cursor,Read};
+use crate::reader::{RecordTerminator,SimpleCsvReader,SimpleCsvReaderOptions,Strictness};
+use crate::writer::NewlineType;
+
+// Candidates in order of preference, which decides ties
+const DELIMITERS: [char; 5] = [',', '\t', ';', '|', ':'];
+const QUOTES: [char; 2] = ['"', '\''];
+
+/// Guesses the dialect of CSV input from a sample of its first bytes.
+///
+/// Every likely combination of delimiter, quote and escape is tried on the sample with the reader itself.
+/// The one that gives the most rows with the same number of fields and the fewest departures from RFC 4180 wins.
+/// Ties go to the earlier candidate: `,` `\t` `;` `|` `:` as the delimiter, then `"`, `'` or no quotes,
+/// and doubled quotes before `\` escapes.
+///
+/// A header row is assumed when the first row does not look like the rest,
+/// for example text above a column of numbers or above values that all have the same length.
+pub struct Sniffer {
+    /// How many bytes to read from the start of the input
+    pub sample_size: usize
+}
+
+impl Default for Sniffer {
+    fn default() -> Sniffer {
+        Sniffer {
+            sample_size: 64 * 1024
+        }
+    }
+}
+
+/// What `Sniffer` found out about its sample
+#[derive(Clone)]
+pub struct Sniffed {
+    /// Reader options for the delimiter, quote, escape, line endings and header row that were found
+    pub options: SimpleCsvReaderOptions,
+    /// The most common line ending in the sample, to write files in the same style.
+    /// `None` if the sample holds a single line
+    pub newline_type: Option<NewlineType>,
+    /// How well `options` fit the sample, from 0 to 1. Grows with the number of rows in the sample
+    pub confidence: f64
+}
+
+/// The input returned by `Sniffer::sniff`: the sample, then the rest of the original reader
+pub type Replay<R> = Chain<Cursor<Vec<u8>>,R>;
+
+// How well one set of options parsed the sample
+struct Trial {
+    score: f64,
+    rows: usize
+}
+
+impl Sniffer {
+    pub fn new() -> Sniffer {
+        Default::default()
+    }
+
+    /// Reads up to `sample_size` bytes from `input` and guesses the dialect from them.
+    /// Also returns a reader that yields the sample again, followed by the rest of `input`.
+    pub fn sniff<R: BufRead>(&self, mut input: R) -> io::Result<(Sniffed, Replay<R>)> {
+        let mut sample = Vec::new();
+        input.by_ref().take(self.sample_size as u64).read_to_end(&mut sample)?;
+        // The sample only cuts the last record short if the input goes on after it
+        let truncated = sample.len() == self.sample_size && loop {
+            match input.fill_buf() {
+                Ok(rest) => break !rest.is_empty(),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e)
+            }
+        };
+        let sniffed = self.guess(&sample,truncated);
+        Ok((sniffed, Cursor::new(sample).chain(input)))
+    }
+
+    /// Guesses the dialect from the first `sample_size` bytes of `input`
+    pub fn sniff_bytes(&self, input: &[u8]) -> Sniffed {
+        let len = input.len().min(self.sample_size);
+        self.guess(&input[..len],len < input.len())
+    }
+
+    // With `truncated`, the last record in the sample may be cut short and is left out
+    fn guess(&self, sample: &[u8], truncated: bool) -> Sniffed {
+        let (terminator, newline_type) = line_endings(sample);
+
+        let mut quotes: Vec<Option<char>> = QUOTES.iter().cloned()
+            .filter(|&quote| quote == '"' || sample.contains(&(quote as u8)))
+            .map(Some)
+            .collect();
+        // Without any quotes in the sample, every choice parses the same
+        if QUOTES.iter().any(|&quote| sample.contains(&(quote as u8))) {
+            quotes.push(None);
+        }
+        let mut escapes = vec![None];
+        if sample.contains(&b'\\') {
+            escapes.push(Some('\\'));
+        }
+
+        let mut best: Option<(Trial, SimpleCsvReaderOptions)> = None;
+        for &delimiter in DELIMITERS.iter() {
+            for &text_enclosure in quotes.iter() {
+                for &escape in escapes.iter() {
+                    let options = SimpleCsvReaderOptions {
+                        delimiter,
+                        text_enclosure,
+                        escape,
+                        terminator: terminator.clone(),
+                        strictness: Strictness::Warn,
+                        ..Default::default()
+                    };
+                    let trial = trial(sample,&options,truncated);
+                    let better = match best {
+                        Some((ref best, _)) => trial.score > best.score,
+                        None => true
+                    };
+                    if better {
+                        best = Some((trial,options));
+                    }
+                }
+            }
+        }
+        let (trial, mut options) = best.expect("there is always a candidate");
+
+        let mut rows = SimpleCsvReader::with_options(sample,options.clone())
+            .filter_map(|row| row.ok())
+            .collect::<Vec<_>>();
+        if truncated {
+            rows.pop();
+        }
+        options.has_headers = has_headers(&rows);
+        options.strictness = Strictness::Lenient;
+
+        Sniffed {
+            options,
+            newline_type,
+            confidence: trial.score * (1.0 - 1.0 / (trial.rows as f64 + 1.0))
+        }
+    }
+}
+
+// Counts the line endings in the sample. Quoted newlines are counted too, which rarely changes the outcome
+fn line_endings(sample: &[u8]) -> (RecordTerminator, Option<NewlineType>) {
+    let (mut lf, mut crlf, mut cr) = (0usize, 0usize, 0usize);
+    let mut bytes = sample.iter().peekable();
+    while let Some(&b) = bytes.next() {
+        match b {
+            b'\n' => lf += 1,
+            b'\r' if bytes.peek() == Some(&&b'\n') => {
+                bytes.next();
+                crlf += 1;
+            },
+            b'\r' => cr += 1,
+            _ => {}
+        }
+    }
+    let terminator = match (cr > 0, lf + crlf > 0) {
+        (true, false) => RecordTerminator::Cr,
+        (true, true) => RecordTerminator::Any,
+        _ => RecordTerminator::Newline
+    };
+    let newline_type = if lf + crlf + cr == 0 {
+        None
+    } else if lf >= crlf && lf >= cr {
+        Some(NewlineType::UnixStyle)
+    } else if crlf >= cr {
+        Some(NewlineType::WindowsStyle)
+    } else {
+        Some(NewlineType::Custom("\r".to_string()))
+    };
+    (terminator, newline_type)
+}
+
+// Scores how consistently `options` split the sample into rows: the share of rows with the most common
+// number of fields times the share of rows without violations, halved if that number is one.
+fn trial(sample: &[u8], options: &SimpleCsvReaderOptions, truncated: bool) -> Trial {
+    let mut reader = SimpleCsvReader::with_options(sample,options.clone());
+    let mut rows = Vec::new();
+    while let Some(Ok(record)) = reader.next_byte_record() {
+        rows.push((record.len(),reader.warnings().is_empty()));
+    }
+    if truncated {
+        rows.pop();
+    }
+    if rows.is_empty() {
+        return Trial { score: 0.0, rows: 0 };
+    }
+
+    let mut counts: HashMap<usize,usize> = HashMap::new();
+    for &(fields, _) in rows.iter() {
+        *counts.entry(fields).or_insert(0) += 1;
+    }
+    // The most common number of fields, and the larger one on a tie
+    let (fields, matching) = counts.into_iter().max_by_key(|&(fields, count)| (count,fields)).unwrap();
+    let clean = rows.iter().filter(|&&(_, clean)| clean).count();
+
+    let total = rows.len() as f64;
+    let mut score = (matching as f64 / total) * (clean as f64 / total);
+    if fields < 2 {
+        score /= 2.0;
+    }
+    Trial { score, rows: rows.len() }
+}
+
+// Votes column by column on whether the first row names the columns rather than holding data
+fn has_headers(rows: &[Vec<String>]) -> bool {
+    let (header, body) = match rows.split_first() {
+        Some((header, body)) if !body.is_empty() => (header, body),
+        _ => return false
+    };
+    // Column names are neither blank nor repeated
+    let mut names = HashSet::new();
+    if header.iter().any(|name| name.trim().is_empty() || !names.insert(name)) {
+        return false;
+    }
+
+    let mut votes = 0i32;
+    for (index, name) in header.iter().enumerate() {
+        let values: Vec<&str> = body.iter()
+            .filter_map(|row| row.get(index))
+            .map(|value| value.as_str())
+            .filter(|value| !value.is_empty())
+            .collect();
+        if values.is_empty() {
+            continue;
+        }
+        if values.iter().all(|value| is_number(value)) {
+            votes += if is_number(name) { -1 } else { 1 };
+            continue;
+        }
+        let len = values[0].chars().count();
+        if values.iter().all(|value| value.chars().count() == len) {
+            votes += if name.chars().count() == len { -1 } else { 1 };
+        }
+    }
+    votes > 0
+}
+
+fn is_number(value: &str) -> bool {
+    value.trim().parse::<f64>().is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+
+    #[test]
+    fn sniffer_finds_dialect() {
+        let input = "name;age;city\r\n\"Smith; J\";42;\"Paris\"\r\nDoe;7;Rome\r\n";
+        let sniffed = Sniffer::new().sniff_bytes(input.as_bytes());
+        assert_eq!(sniffed.options.delimiter, ';');
+        assert_eq!(sniffed.options.text_enclosure, Some('"'));
+        assert_eq!(sniffed.options.escape, None);
+        assert!(sniffed.options.has_headers);
+        assert_eq!(sniffed.options.terminator, RecordTerminator::Newline);
+        assert_eq!(sniffed.newline_type, Some(NewlineType::WindowsStyle));
+        assert!(sniffed.confidence > 0.7);
+
+        // Quotes that are ordinary characters, and no header above text of varying length
+        let input = "1\t5\" screen\n2\t\"big\" deal\n3\tsmall\n";
+        let sniffed = Sniffer::new().sniff_bytes(input.as_bytes());
+        assert_eq!(sniffed.options.delimiter, '\t');
+        assert_eq!(sniffed.options.text_enclosure, None);
+        assert!(!sniffed.options.has_headers);
+
+        let input = "1,\"a \\\"b\\\"\",x\n2,\"c \\\"d\\\" e\",y\n3,\"\\\"\",z\n";
+        let sniffed = Sniffer::new().sniff_bytes(input.as_bytes());
+        assert_eq!(sniffed.options.delimiter, ',');
+        assert_eq!(sniffed.options.escape, Some('\\'));
+
+        // A single column leaves the delimiter in doubt
+        let single = Sniffer::new().sniff_bytes(b"a\nb\nc\n");
+        let table = Sniffer::new().sniff_bytes(b"a,1\nb,2\nc,3\n");
+        assert!(single.confidence < table.confidence);
+    }
+
+    #[test]
+    fn sniffer_keeps_sample() {
+        let input = "id|name\r1|\"a\rb\"\r2|c\r3|d\r".repeat(3);
+        let sniffer = Sniffer { sample_size: 24 };
+        let (sniffed, rest) = sniffer.sniff(BufReader::with_capacity(5,input.as_bytes())).unwrap();
+        assert_eq!(sniffed.options.delimiter, '|');
+        assert_eq!(sniffed.options.terminator, RecordTerminator::Cr);
+        assert_eq!(sniffed.newline_type, Some(NewlineType::Custom("\r".to_string())));
+        assert!(sniffed.options.has_headers);
+
+        let mut reader = SimpleCsvReader::with_options(rest,sniffed.options);
+        assert_eq!(reader.headers().unwrap().unwrap().index_of("name"), Ok(1));
+        let rows: Vec<_> = reader.map(|row| row.unwrap()).collect();
+        assert_eq!(rows.len(), 11);
+        assert_eq!(rows[0], vec!["1".to_string(),"a\rb".to_string()]);
+        assert_eq!(rows[10], vec!["3".to_string(),"d".to_string()]);
+    }
+
+    #[test]
+    fn sniffer_sample_of_whole_input() {
+        // An input of exactly `sample_size` bytes keeps its last record
+        let input = "a;b\n1;2\n3;4";
+        let sniffer = Sniffer { sample_size: input.len() };
+        let (sniffed, _) = sniffer.sniff(BufReader::with_capacity(4,input.as_bytes())).unwrap();
+        let whole = Sniffer::new().sniff_bytes(input.as_bytes());
+        assert_eq!(sniffed.options.delimiter, ';');
+        assert_eq!(sniffed.confidence, whole.confidence);
+        assert_eq!(sniffer.sniff_bytes(input.as_bytes()).confidence, whole.confidence);
+
+        // With more input after it, the last record of the sample is left out
+        let (cut, _) = Sniffer { sample_size: input.len() - 1 }.sniff(input.as_bytes()).unwrap();
+        assert!(cut.confidence < whole.confidence);
+    }
+}
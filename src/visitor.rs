@@ -0,0 +1,152 @@
+use crate::error::Position;
+
+/// Receives the fields of each row as they are parsed, see `SimpleCsvReader::visit`.
+///
+/// Fields are borrowed from a buffer that only ever holds the field being parsed and is reused for every field,
+/// so no memory is allocated per row or per field, and rows of any width take no more memory than their longest field.
+pub trait Visitor {
+    /// Called with the raw bytes of each field, in order, as soon as the field is complete. Quotes around the field have been removed
+    fn on_field(&mut self, field: &[u8], info: FieldInfo);
+    /// Called after the last field of each row that was read without an error
+    fn on_record_end(&mut self, info: RecordInfo);
+}
+
+/// Describes a field passed to `Visitor::on_field`
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub struct FieldInfo {
+    /// Column of the field, starting at 0
+    pub index: usize,
+    /// Whether the field was enclosed in quotes
+    pub quoted: bool,
+    /// Where the field starts in the input
+    pub position: Position
+}
+
+/// Describes a row passed to `Visitor::on_record_end`
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub struct RecordInfo {
+    /// Where the row starts in the input
+    pub position: Position,
+    /// Number of fields in the row
+    pub fields: usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{self,BufReader,Read};
+    use crate::error::Error;
+    use crate::reader::{ColumnCount,SimpleCsvReader,SimpleCsvReaderOptions,Strictness};
+
+    #[derive(Default)]
+    struct Collector {
+        fields: Vec<(Vec<u8>,FieldInfo)>,
+        records: Vec<RecordInfo>
+    }
+
+    impl Visitor for Collector {
+        fn on_field(&mut self, field: &[u8], info: FieldInfo) {
+            self.fields.push((field.to_vec(),info));
+        }
+
+        fn on_record_end(&mut self, info: RecordInfo) {
+            self.records.push(info);
+        }
+    }
+
+    #[test]
+    fn visitor_fields_and_records() {
+        let input = b"id,name\r\n1,\"a\r\nb\"\r\n\"2\",\xff";
+        let csv_options = SimpleCsvReaderOptions { has_headers: true, ..Default::default() };
+        let mut reader = SimpleCsvReader::with_options(&input[..],csv_options);
+        let mut collector = Collector::default();
+        reader.visit(&mut collector).unwrap();
+
+        let fields: Vec<(&[u8],usize,bool,u64)> = collector.fields.iter()
+            .map(|&(ref field, info)| (&field[..],info.index,info.quoted,info.position.byte))
+            .collect();
+        assert_eq!(fields, vec![
+            (&b"1"[..],0,false,9),
+            (&b"a\r\nb"[..],1,true,11),
+            (&b"2"[..],0,true,19),
+            (&b"\xff"[..],1,false,23)]);
+        assert_eq!(collector.records, vec![
+            RecordInfo { position: Position { byte: 9, line: 2, record: 1 }, fields: 2 },
+            RecordInfo { position: Position { byte: 19, line: 4, record: 2 }, fields: 2 }]);
+    }
+
+    #[test]
+    fn visitor_padded_fields() {
+        let csv_options = SimpleCsvReaderOptions { column_count: ColumnCount::Pad, ..Default::default() };
+        let mut reader = SimpleCsvReader::with_options(&b"1,2,3\n\"4\""[..],csv_options);
+        let mut collector = Collector::default();
+        reader.visit(&mut collector).unwrap();
+
+        // Padding is unquoted and starts where the last field does
+        let fields: Vec<(&[u8],bool,u64)> = collector.fields[3..].iter()
+            .map(|&(ref field, info)| (&field[..],info.quoted,info.position.byte))
+            .collect();
+        assert_eq!(fields, vec![(&b"4"[..],true,6),(&b""[..],false,6),(&b""[..],false,6)]);
+        assert_eq!(collector.records[1].fields, 3);
+    }
+
+    #[test]
+    fn visitor_stops_at_strict_error() {
+        let input = b"1,\"2\"x\n3,4";
+        let csv_options = SimpleCsvReaderOptions { strictness: Strictness::Strict, ..Default::default() };
+        let mut reader = SimpleCsvReader::with_options(&input[..],csv_options);
+        let mut collector = Collector::default();
+
+        // The field before the error has been passed, the failed field and the end of the row have not
+        match reader.visit(&mut collector) {
+            Err(Error::UnexpectedDataAfterQuote { pos }) => assert_eq!(pos.byte, 5),
+            _ => panic!("expected an error")
+        }
+        assert_eq!(collector.fields.len(), 1);
+        assert!(collector.records.is_empty());
+        reader.visit(&mut collector).unwrap();
+        assert_eq!(collector.records.len(), 1);
+        assert_eq!(collector.fields[2].0, b"4");
+    }
+
+    // Hands out `data` and then fails, as a connection that drops in the middle of a row would
+    struct Broken<'a> {
+        data: &'a [u8]
+    }
+
+    impl<'a> Read for Broken<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.data.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::ConnectionReset,"gone"));
+            }
+            let len = self.data.read(buf)?;
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn visitor_gets_fields_before_the_row_ends() {
+        let input = BufReader::with_capacity(4,Broken { data: b"a,bcdefgh,\"i\nj\",k" });
+        let mut reader = SimpleCsvReader::new(input);
+        let mut collector = Collector::default();
+        match reader.visit(&mut collector) {
+            Err(Error::Io { pos, .. }) => assert_eq!(pos.byte, 17),
+            _ => panic!("expected an I/O error")
+        }
+        let fields: Vec<&[u8]> = collector.fields.iter().map(|(field, _)| &field[..]).collect();
+        assert_eq!(fields, vec![&b"a"[..],b"bcdefgh",b"i\nj"]);
+        assert!(collector.records.is_empty());
+    }
+
+    #[test]
+    fn visitor_truncated_fields() {
+        let csv_options = SimpleCsvReaderOptions { column_count: ColumnCount::Truncate, ..Default::default() };
+        let mut reader = SimpleCsvReader::with_options(&b"1,2\n3,4,5\n6,7"[..],csv_options);
+        let mut collector = Collector::default();
+        reader.visit(&mut collector).unwrap();
+
+        let fields: Vec<&[u8]> = collector.fields.iter().map(|(field, _)| &field[..]).collect();
+        assert_eq!(fields, vec![&b"1"[..],b"2",b"3",b"4",b"6",b"7"]);
+        assert_eq!(collector.records.iter().map(|record| record.fields).collect::<Vec<_>>(), vec![2,2,2]);
+    }
+}
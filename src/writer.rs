@@ -1,26 +1,147 @@
+use std::borrow::Cow;
 use std::default::Default;
-use std::io::{Result,Write};
+use std::io::{Error,ErrorKind,Result,Write};
+use std::result;
 use std::vec::Vec;
+use crate::options::{check_literals,OptionsError};
+use crate::reader::ColumnCount;
 
+#[derive(Clone,Debug,PartialEq,Eq)]
 pub enum NewlineType {
     UnixStyle,
     WindowsStyle,
     Custom(String)
 }
 
+/// What the writer does with a field holding the delimiter or a line ending when `text_enclosure` is `None`
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum UnquotedPolicy {
+    /// Fail with an `ErrorKind::InvalidInput` error before any of the row is written, unless `escape` is set
+    Reject,
+    /// Escape them with `escape`, which has to be set
+    Escape
+}
+
 pub struct SimpleCsvWriterOptions {
     pub delimiter: char,
-    pub text_enclosure: char,
-    pub newline_type: NewlineType
+    /// The quote around fields that need it. With `None` fields are never quoted, see `unquoted_policy`
+    pub text_enclosure: Option<char>,
+    pub newline_type: NewlineType,
+    pub unquoted_policy: UnquotedPolicy,
+    /// Quote fields that start or end with a space or tab, so that a reader trimming whitespace keeps it
+    pub quote_whitespace: bool,
+    /// Escape quotes, delimiters and line endings with this character instead of quoting the field and doubling quotes
+    pub escape: Option<char>,
+    /// End the last row with a line ending too, instead of only separating rows with one
+    pub trailing_newline: bool,
+    /// Hold every row to the number of fields in the first row written.
+    /// A row that does not fit fails with an `ErrorKind::InvalidInput` error and nothing of it is written
+    pub column_count: ColumnCount,
+    /// What `write_nullable` writes for `None`, such as `\N`. With the default empty string `None` is a bare empty field.
+    /// `Some` fields that would read back as null, like `Some("")`, are quoted
+    pub null: String
 }
 
 impl Default for SimpleCsvWriterOptions {
     fn default() -> SimpleCsvWriterOptions {
         SimpleCsvWriterOptions {
             delimiter: ',',
-            text_enclosure: '"',
-            newline_type: NewlineType::UnixStyle
+            text_enclosure: Some('"'),
+            newline_type: NewlineType::UnixStyle,
+            unquoted_policy: UnquotedPolicy::Reject,
+            quote_whitespace: false,
+            escape: None,
+            trailing_newline: false,
+            column_count: ColumnCount::Flexible,
+            null: String::new()
+        }
+    }
+}
+
+impl SimpleCsvWriterOptions {
+    /// Starts from the default options and checks the result in `build`
+    pub fn builder() -> SimpleCsvWriterOptionsBuilder {
+        SimpleCsvWriterOptionsBuilder { options: Default::default() }
+    }
+
+    /// Checks that the delimiter, quote, escape and a custom newline all differ
+    /// and that only the newline holds line ending characters.
+    /// An escape that is the quote is allowed, and doubles quotes as no escape would
+    pub fn validate(&self) -> result::Result<(), OptionsError> {
+        let mut literals = vec![("delimiter",self.delimiter.to_string())];
+        let escape = self.escape.filter(|&c| Some(c) != self.text_enclosure);
+        let characters = [("text_enclosure",self.text_enclosure), ("escape",escape)];
+        for &(option, c) in characters.iter() {
+            if let Some(c) = c {
+                literals.push((option,c.to_string()));
+            }
+        }
+        if self.unquoted_policy == UnquotedPolicy::Escape && self.escape.is_none() {
+            return Err(OptionsError::Missing("escape","unquoted_policy"));
         }
+        let newline = match self.newline_type {
+            NewlineType::Custom(ref newline) => Some(("newline_type",newline.clone())),
+            _ => None
+        };
+        check_literals(&literals,newline)
+    }
+}
+
+/// Sets `SimpleCsvWriterOptions` one by one and refuses combinations that could not be read back
+pub struct SimpleCsvWriterOptionsBuilder {
+    options: SimpleCsvWriterOptions
+}
+
+impl SimpleCsvWriterOptionsBuilder {
+    pub fn delimiter(mut self, delimiter: char) -> SimpleCsvWriterOptionsBuilder {
+        self.options.delimiter = delimiter;
+        self
+    }
+
+    pub fn text_enclosure(mut self, text_enclosure: Option<char>) -> SimpleCsvWriterOptionsBuilder {
+        self.options.text_enclosure = text_enclosure;
+        self
+    }
+
+    pub fn newline_type(mut self, newline_type: NewlineType) -> SimpleCsvWriterOptionsBuilder {
+        self.options.newline_type = newline_type;
+        self
+    }
+
+    pub fn unquoted_policy(mut self, unquoted_policy: UnquotedPolicy) -> SimpleCsvWriterOptionsBuilder {
+        self.options.unquoted_policy = unquoted_policy;
+        self
+    }
+
+    pub fn quote_whitespace(mut self, quote_whitespace: bool) -> SimpleCsvWriterOptionsBuilder {
+        self.options.quote_whitespace = quote_whitespace;
+        self
+    }
+
+    pub fn escape(mut self, escape: Option<char>) -> SimpleCsvWriterOptionsBuilder {
+        self.options.escape = escape;
+        self
+    }
+
+    pub fn trailing_newline(mut self, trailing_newline: bool) -> SimpleCsvWriterOptionsBuilder {
+        self.options.trailing_newline = trailing_newline;
+        self
+    }
+
+    pub fn column_count(mut self, column_count: ColumnCount) -> SimpleCsvWriterOptionsBuilder {
+        self.options.column_count = column_count;
+        self
+    }
+
+    pub fn null(mut self, null: &str) -> SimpleCsvWriterOptionsBuilder {
+        self.options.null = null.to_string();
+        self
+    }
+
+    /// Returns the options, or the first problem `SimpleCsvWriterOptions::validate` finds with them
+    pub fn build(self) -> result::Result<SimpleCsvWriterOptions, OptionsError> {
+        self.options.validate()?;
+        Ok(self.options)
     }
 }
 
@@ -29,7 +150,9 @@ impl Default for SimpleCsvWriterOptions {
 pub struct SimpleCsvWriter<W: Write> {
     options: SimpleCsvWriterOptions,
     writer: W,
-    row_written: bool
+    row_written: bool,
+    // The number of fields in the first row written, for `column_count`
+    columns: Option<usize>
 }
 
 impl<W: Write> SimpleCsvWriter<W> {
@@ -40,9 +163,10 @@ impl<W: Write> SimpleCsvWriter<W> {
     
     pub fn with_options(writer: W, options: SimpleCsvWriterOptions) -> SimpleCsvWriter<W> {
         SimpleCsvWriter {
-            options: options,
-            writer: writer,
-            row_written: false
+            options,
+            writer,
+            row_written: false,
+            columns: None
         }
     }
     
@@ -51,81 +175,237 @@ impl<W: Write> SimpleCsvWriter<W> {
     }
     
     pub fn write(&mut self, row: &[String]) -> Result<()> {
-        let delimiter = self.options.delimiter;
-        let text_enclosure = self.options.text_enclosure;
-        let mut col_number = 0usize;
+        self.write_row(row)
+    }
+
+    /// Like `write`, but writes `None` as `null`, telling it apart from an empty string
+    pub fn write_nullable(&mut self, row: &[Option<String>]) -> Result<()> {
+        self.write_row(row)
+    }
+
+    fn write_row<F: Field>(&mut self, row: &[F]) -> Result<()> {
+        let row = fit_row(&self.options,self.columns,row)?;
         // Only write newline if we have already written at least one row
-        if self.row_written {
-            match self.options.newline_type {
-                NewlineType::UnixStyle => {
-                    try!(self.writer.write_all(b"\n"));
-                },
-                NewlineType::WindowsStyle => {
-                    try!(self.writer.write_all(b"\r\n"));
-                },
-                NewlineType::Custom(ref newline_str) => {
-                   try!(self.writer.write_all(newline_str.as_bytes()));
+        encode_row(&mut self.writer,&self.options,&row,self.row_written)?;
+        self.row_written = true;
+        self.columns.get_or_insert(row.len());
+        Ok(())
+    }
+    
+    pub fn write_all(&mut self, rows: &[Vec<String>]) -> Result<()> {
+        for row in rows.iter() {
+            self.write(row)?;
+        }
+        Ok(())
+    }
+}
+
+// A field the writers take: a string, or an optional one where `None` stands for null
+pub(crate) trait Field: Clone + Default {
+    const NULLABLE: bool;
+    fn value(&self) -> Option<&str>;
+}
+
+impl Field for String {
+    const NULLABLE: bool = false;
+    fn value(&self) -> Option<&str> {
+        Some(self)
+    }
+}
+
+impl Field for Option<String> {
+    const NULLABLE: bool = true;
+    fn value(&self) -> Option<&str> {
+        self.as_deref()
+    }
+}
+
+// Pads or truncates `row` to `columns`, the number of fields in the first row written,
+// or fails if `column_count` does not allow that. Padding is empty, or null for nullable rows.
+// Until a row has been written, any number of fields fits
+pub(crate) fn fit_row<'a, F: Field>(options: &SimpleCsvWriterOptions, columns: Option<usize>, row: &'a [F]) -> Result<Cow<'a,[F]>> {
+    if options.column_count == ColumnCount::Flexible {
+        return Ok(Cow::Borrowed(row));
+    }
+    let found = row.len();
+    let expected = columns.unwrap_or(found);
+    match options.column_count {
+        _ if found == expected => Ok(Cow::Borrowed(row)),
+        ColumnCount::Pad if found < expected => {
+            let mut padded = row.to_vec();
+            padded.resize(expected,F::default());
+            Ok(Cow::Owned(padded))
+        },
+        ColumnCount::Truncate if found > expected => Ok(Cow::Borrowed(&row[..expected])),
+        _ => Err(Error::new(ErrorKind::InvalidInput,format!("expected {} fields but the row has {}",expected,found)))
+    }
+}
+
+// Writes `row` with its fields quoted where needed, preceded by a line ending if `after_row` is set,
+// or followed by one with `trailing_newline`.
+// Shared by the writers so that they all quote the same way.
+pub(crate) fn encode_row<W: Write, F: Field>(writer: &mut W, options: &SimpleCsvWriterOptions, row: &[F], after_row: bool) -> Result<()> {
+    let delimiter = options.delimiter;
+    let escape = match options.text_enclosure {
+        Some(text_enclosure) => options.escape.filter(|&c| c != text_enclosure),
+        None => match options.escape {
+            Some(escape) => Some(escape),
+            None => {
+                if let Some(column) = row.iter().filter_map(Field::value).find(|column| needs_quotes(options,column)) {
+                    let reason = match options.unquoted_policy {
+                        UnquotedPolicy::Reject => "quoting is disabled",
+                        UnquotedPolicy::Escape => "no escape is set"
+                    };
+                    return Err(Error::new(ErrorKind::InvalidInput,format!("field {:?} needs quotes, but {}",column,reason)));
                 }
+                None
             }
-            
         }
-        for column in row.iter() {
-            if col_number != 0 {
-                try!(write!(&mut self.writer,"{}",delimiter));
+    };
+    // Without quotes, a field equal to the null marker can only be told apart from null if escaping changes it
+    if F::NULLABLE && options.text_enclosure.is_none() {
+        let ambiguous = |column: &&str| *column == options.null && !matches!(escape, Some(escape) if options.null.contains(escape));
+        if let Some(column) = row.iter().filter_map(Field::value).find(ambiguous) {
+            return Err(Error::new(ErrorKind::InvalidInput,format!("field {:?} would be read back as null, but quoting is disabled",column)));
+        }
+    }
+    if after_row && !options.trailing_newline {
+        write_newline(writer,options)?;
+    }
+    for (col_number, field) in row.iter().enumerate() {
+        if col_number != 0 {
+            write!(&mut *writer,"{}",delimiter)?;
+        }
+        let column = match field.value() {
+            Some(column) => column,
+            None => {
+                writer.write_all(options.null.as_bytes())?;
+                continue;
             }
-            let mut is_quoted = false;
-            let mut char_iterator = column.char_indices();
-            let mut char_option = char_iterator.next();
-            while let Some((byte_index, c)) = char_option {
-                match is_quoted {
-                   false => {
-                            if c == text_enclosure || c == delimiter || c == '\n' || c == '\r'{
-                                is_quoted = true;
-                                try!(write!(&mut self.writer,"{}",text_enclosure));
-                                try!(self.writer.write_all(column[..byte_index].as_bytes()));
-                                // Short circuit the loop so the iterator does not get incremented
-                                continue;
-                            }
-                    },
-                    true => {
-                         match c {
-                            _ if c == text_enclosure  => {
-                                try!(write!(&mut self.writer,"{}",c));
-                                try!(write!(&mut self.writer,"{}",c));
-                            },
-                            _ => {
-                                try!(write!(&mut self.writer,"{}",c));
-                            }
-                        }
-                    }
-                }
-                // Go to the next char
-                char_option = char_iterator.next();
+        };
+        // An empty string or the null marker would be read back as null
+        let quote = F::NULLABLE && (column.is_empty() || column == options.null);
+        if let Some(escape) = escape {
+            encode_escaped(writer,options,escape,column,quote)?;
+            continue;
+        }
+        let text_enclosure = match options.text_enclosure {
+            Some(text_enclosure) => text_enclosure,
+            None => {
+                writer.write_all(column.as_bytes())?;
+                continue;
             }
+        };
+        let mut is_quoted = false;
+        // A custom newline inside a field would end the row when it is read back
+        let has_newline = match options.newline_type {
+            NewlineType::Custom(ref newline) => !newline.is_empty() && column.contains(newline.as_str()),
+            _ => false
+        };
+        if quote || has_newline || (options.quote_whitespace && (column.starts_with(is_whitespace) || column.ends_with(is_whitespace))) {
+            is_quoted = true;
+            write!(&mut *writer,"{}",text_enclosure)?;
+        }
+        let mut char_iterator = column.char_indices();
+        let mut char_option = char_iterator.next();
+        while let Some((byte_index, c)) = char_option {
             match is_quoted {
-                false => {
-                    try!(self.writer.write_all(column.as_bytes()));
+               false => {
+                        if c == text_enclosure || c == delimiter || c == '\n' || c == '\r'{
+                            is_quoted = true;
+                            write!(&mut *writer,"{}",text_enclosure)?;
+                            writer.write_all(&column.as_bytes()[..byte_index])?;
+                            // Short circuit the loop so the iterator does not get incremented
+                            continue;
+                        }
                 },
                 true => {
-                    try!(write!(&mut self.writer,"{}",text_enclosure));
+                     match c {
+                        _ if c == text_enclosure  => {
+                            write!(&mut *writer,"{}",c)?;
+                            write!(&mut *writer,"{}",c)?;
+                        },
+                        _ => {
+                            write!(&mut *writer,"{}",c)?;
+                        }
+                    }
                 }
             }
-            col_number += 1;
+            // Go to the next char
+            char_option = char_iterator.next();
         }
-        self.row_written = true;
-        Ok(())
-    }
-        
-    
-    pub fn write_all(&mut self, rows: &[Vec<String>]) -> Result<()> {
-        for row in rows.iter() {
-            try!(self.write(&*row));
+        match is_quoted {
+            false => {
+                writer.write_all(column.as_bytes())?;
+            },
+            true => {
+                write!(&mut *writer,"{}",text_enclosure)?;
+            }
         }
-        Ok(())
     }
+    if options.trailing_newline {
+        write_newline(writer,options)?;
+    }
+    Ok(())
+}
+
+fn write_newline<W: Write>(writer: &mut W, options: &SimpleCsvWriterOptions) -> Result<()> {
+    match options.newline_type {
+        NewlineType::UnixStyle => writer.write_all(b"\n"),
+        NewlineType::WindowsStyle => writer.write_all(b"\r\n"),
+        NewlineType::Custom(ref newline_str) => writer.write_all(newline_str.as_bytes())
+    }
+}
+
+// Writes `column` with every character that would need quoting escaped instead.
+// The field is only enclosed when `quote` or `quote_whitespace` asks for it.
+fn encode_escaped<W: Write>(writer: &mut W, options: &SimpleCsvWriterOptions, escape: char, column: &str, quote: bool) -> Result<()> {
+    let enclose = options.text_enclosure.filter(|_| quote || (options.quote_whitespace && (column.starts_with(is_whitespace) || column.ends_with(is_whitespace))));
+    if let Some(text_enclosure) = enclose {
+        write!(&mut *writer,"{}",text_enclosure)?;
+    }
+    // Escaping the first character of a custom newline keeps it from ending the row
+    let newline_start = match options.newline_type {
+        NewlineType::Custom(ref newline) => newline.chars().next(),
+        _ => None
+    };
+    // Copy the text between escapes in one go
+    let mut start = 0usize;
+    for (byte_index, c) in column.char_indices() {
+        let escaped = match c {
+            '\n' => 'n',
+            '\r' => 'r',
+            '\t' => 't',
+            '\0' => '0',
+            _ if Some(c) == options.text_enclosure || c == options.delimiter || c == escape || Some(c) == newline_start => c,
+            _ => continue
+        };
+        writer.write_all(&column.as_bytes()[start..byte_index])?;
+        write!(&mut *writer,"{}{}",escape,escaped)?;
+        start = byte_index + c.len_utf8();
+    }
+    writer.write_all(&column.as_bytes()[start..])?;
+    if let Some(text_enclosure) = enclose {
+        write!(&mut *writer,"{}",text_enclosure)?;
+    }
+    Ok(())
+}
+
+// Whether `column` could not be read back without quotes or escapes
+fn needs_quotes(options: &SimpleCsvWriterOptions, column: &str) -> bool {
+    let has_newline = match options.newline_type {
+        NewlineType::Custom(ref newline) => !newline.is_empty() && column.contains(newline.as_str()),
+        _ => false
+    };
+    has_newline || column.contains([options.delimiter, '\n', '\r'])
+}
+
+fn is_whitespace(c: char) -> bool {
+    c == ' ' || c == '\t'
 }
 
 #[cfg(test)]
+#[allow(clippy::useless_vec)]
 mod tests { 
     use super::*;
     
@@ -179,7 +459,111 @@ mod tests {
         
         let test_string = "1,\"2\n\",3\n4,\",5\",6";
         assert_eq!(vec, test_string.as_bytes());
-        
+
+        let options = SimpleCsvWriterOptions { newline_type: NewlineType::WindowsStyle, trailing_newline: true, ..Default::default() };
+        let mut writer = SimpleCsvWriter::with_options(Vec::new(),options);
+        writer.write_all(&[vec!["1".to_string()],vec!["2".to_string()]]).unwrap();
+        assert_eq!(writer.as_inner(), b"1\r\n2\r\n");
+    }
+
+    #[test]
+    fn writer_column_count_test() {
+        let row = |fields: &[&str]| fields.iter().map(|field| field.to_string()).collect::<Vec<_>>();
+        let options = SimpleCsvWriterOptions { column_count: ColumnCount::Exact, ..Default::default() };
+        let mut writer = SimpleCsvWriter::with_options(Vec::new(),options);
+        writer.write(&row(&["a","b"])).unwrap();
+        let error = writer.write(&row(&["c"])).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert_eq!(error.to_string(), "expected 2 fields but the row has 1");
+        writer.write(&row(&["d","e"])).unwrap();
+        assert_eq!(writer.as_inner(), b"a,b\nd,e");
+
+        let options = SimpleCsvWriterOptions { column_count: ColumnCount::Pad, ..Default::default() };
+        let mut writer = SimpleCsvWriter::with_options(Vec::new(),options);
+        writer.write_all(&[row(&["a","b","c"]),row(&["d"])]).unwrap();
+        assert!(writer.write(&row(&["e","f","g","h"])).is_err());
+        assert_eq!(writer.as_inner(), b"a,b,c\nd,,");
+
+        let options = SimpleCsvWriterOptions { column_count: ColumnCount::Truncate, ..Default::default() };
+        let mut writer = SimpleCsvWriter::with_options(Vec::new(),options);
+        writer.write_all(&[row(&["a"]),row(&["b","c"])]).unwrap();
+        assert!(writer.write(&[]).is_err());
+        assert_eq!(writer.as_inner(), b"a\nb");
+
+        // A first row that is rejected does not set the number of fields
+        let options = SimpleCsvWriterOptions { column_count: ColumnCount::Exact, text_enclosure: None, ..Default::default() };
+        let mut writer = SimpleCsvWriter::with_options(Vec::new(),options);
+        assert!(writer.write(&row(&["a,b","c","d"])).is_err());
+        writer.write(&row(&["e","f"])).unwrap();
+        assert!(writer.write(&row(&["g","h","i"])).is_err());
+        assert_eq!(writer.as_inner(), b"e,f");
+    }
+
+    #[test]
+    fn writer_nullable_test() {
+        let row = vec![Some("1".to_string()),None,Some("".to_string())];
+        let mut writer = SimpleCsvWriter::new(Vec::new());
+        writer.write_nullable(&row).unwrap();
+        assert_eq!(writer.as_inner(), b"1,,\"\"");
+
+        let options = SimpleCsvWriterOptions { null: "NULL".to_string(), ..Default::default() };
+        let mut writer = SimpleCsvWriter::with_options(Vec::new(),options);
+        writer.write_nullable(&row).unwrap();
+        writer.write_nullable(&[Some("NULL".to_string())]).unwrap();
+        assert_eq!(writer.as_inner(), b"1,NULL,\"\"\n\"NULL\"");
+
+        // Without quotes an empty string would read back as null
+        let options = SimpleCsvWriterOptions { text_enclosure: None, ..Default::default() };
+        let mut writer = SimpleCsvWriter::with_options(Vec::new(),options);
+        let error = writer.write_nullable(&row).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        writer.write_nullable(&row[..2]).unwrap();
+        assert_eq!(writer.as_inner(), b"1,");
+    }
+
+    #[test]
+    fn writer_quote_whitespace_test() {
+        let row = ["1".to_string()," 2".to_string(),"3\t".to_string(),"4 4".to_string()," \"5".to_string()];
+        let mut writer = SimpleCsvWriter::new(Vec::new());
+        let _ = writer.write(&row);
+        assert_eq!(writer.as_inner(), b"1, 2,3\t,4 4,\" \"\"5\"");
+
+        let options = SimpleCsvWriterOptions { quote_whitespace: true, ..Default::default() };
+        let mut writer = SimpleCsvWriter::with_options(Vec::new(),options);
+        let _ = writer.write(&row);
+        assert_eq!(writer.as_inner(), b"1,\" 2\",\"3\t\",4 4,\" \"\"5\"");
+    }
+
+    #[test]
+    fn writer_without_text_enclosure_test() {
+        let options = SimpleCsvWriterOptions { delimiter: '\t', text_enclosure: None, ..Default::default() };
+        let mut writer = SimpleCsvWriter::with_options(Vec::new(),options);
+        writer.write(&["\"1\"".to_string(),"2,\"".to_string()]).unwrap();
+        let error = writer.write(&["3".to_string(),"4\t5".to_string()]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        writer.write(&["6".to_string()]).unwrap();
+        // Nothing of the rejected row is written
+        assert_eq!(writer.as_inner(), b"\"1\"\t2,\"\n6");
+
+        let options = SimpleCsvWriterOptions { delimiter: '\t', text_enclosure: None, unquoted_policy: UnquotedPolicy::Escape, escape: Some('\\'), ..Default::default() };
+        let mut writer = SimpleCsvWriter::with_options(Vec::new(),options);
+        writer.write(&["\"3\\".to_string(),"4\t5\n".to_string()]).unwrap();
+        assert_eq!(writer.as_inner(), b"\"3\\\\\t4\\t5\\n");
+
+        // Escaping needs an escape character, which is not made up when it is missing
+        let options = SimpleCsvWriterOptions { text_enclosure: None, unquoted_policy: UnquotedPolicy::Escape, ..Default::default() };
+        let mut writer = SimpleCsvWriter::with_options(Vec::new(),options);
+        assert_eq!(writer.write(&["a,b".to_string()]).unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert!(writer.as_inner().is_empty());
+    }
+
+    #[test]
+    fn writer_escape_test() {
+        let options = SimpleCsvWriterOptions { escape: Some('\\'), ..Default::default() };
+        let mut writer = SimpleCsvWriter::with_options(Vec::new(),options);
+        let _ = writer.write(&["1".to_string(),"a,\"b\"".to_string(),"c\\d".to_string()]);
+        let _ = writer.write(&["x\r\ny\tz".to_string(),"".to_string(),"\u{00A9}".to_string()]);
+        assert_eq!(writer.as_inner(), "1,a\\,\\\"b\\\",c\\\\d\nx\\r\\ny\\tz,,\u{00A9}".as_bytes());
     }
 }
 